@@ -1,26 +1,861 @@
 #![deny(clippy::all)]
 #![forbid(unsafe_code)]
 
-use log::error;
-use image::{GenericImageView, DynamicImage, Rgba};
-use pixels::{Error, PixelsBuilder, SurfaceTexture};
-use winit::dpi::LogicalSize;
+#[cfg(test)]
+mod testing;
+
+use log::{debug, error, info, warn};
+use rand::{Rng, SeedableRng, distributions::WeightedIndex, rngs::StdRng};
+use serde::{Deserialize, Serialize};
+use image::{AnimationDecoder, GenericImage, GenericImageView, DynamicImage, Rgba, RgbaImage};
+use image::codecs::gif::GifDecoder;
+use image::error::{ImageError, LimitError, LimitErrorKind};
+use pixels::{Error, Pixels, PixelsBuilder, SurfaceTexture};
+use winit::dpi::{LogicalSize, PhysicalSize};
 use winit::event::{Event, VirtualKeyCode};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::WindowBuilder;
 use winit_input_helper::WinitInputHelper;
-use std::{time::{SystemTime, UNIX_EPOCH}, path::Path};
+use std::{collections::{HashMap, VecDeque}, fs, rc::Rc, io::{BufRead, BufReader, BufWriter, Write}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}, path::{Path, PathBuf}};
 
 const WORLD_WIDTH: u32 = 256;
 const WORLD_HEIGHT: u32 = 144;
 
+/// Fastest horizontal speed the player can run at
+const MAX_RUN_SPEED: f32 = 4.75;
+
+/// Frame duration in milliseconds for imported animations that don't specify one
+#[allow(dead_code)]
+const DEFAULT_FRAME_DURATION: u64 = 100;
+
+/// Upward velocity the player jumps with
+const JUMP_VELOCITY: f32 = 7.0;
+
+/// Fastest speed gravity can pull the player to
+const TERMINAL_VELOCITY: f32 = 5.0;
+
+/// Frames taking longer than this multiple of the target frame time count as dropped
+const DROPPED_FRAME_THRESHOLD: f64 = 1.5;
+
+/// How often to look for changed asset files when hot reloading, in milliseconds
+const HOT_RELOAD_INTERVAL: u128 = 500;
+
+/// How far the editor camera pans per physics step, in pixels
+const EDITOR_PAN_SPEED: f32 = 2.0;
+
+/// Largest zoom factor of the editor camera
+const EDITOR_MAX_ZOOM: f32 = 8.0;
+
+/// Color used for the debug sprite bounds outline, the box sprites collide with each other by
+const DEBUG_BOUNDS_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+/// Color used for the debug outline of the box the floor and walls stop, drawn when it differs from the bounds
+const DEBUG_BODY_COLOR: [u8; 4] = [0, 255, 255, 255];
+
+/// Largest number of logical window pixels each world pixel covers
+const WINDOW_SCALE: u32 = 7;
+
+/// How far past the edge of the world a sprite has to be before `despawn_offscreen` removes it
+const OFFSCREEN_DESPAWN_MARGIN: f32 = 32.0;
+
+/// Seed for the world's random number generator, so runs are repeatable unless reseeded
+const DEFAULT_RNG_SEED: u64 = 0;
+
+/// How many times a second the screen shake changes direction
+const SCREEN_SHAKE_FREQUENCY: f64 = 30.0;
+
+/// How many action presses are remembered for combo detection
+const INPUT_HISTORY_LEN: usize = 32;
+
+/// Color of the shadows sprites cast on the floor
+const SHADOW_COLOR: [u8; 4] = [0, 0, 0, 96];
+
+/// Size of the checkerboard squares behind contact sheet frames, and their two colors
+#[allow(dead_code)]
+const CHECKER_SIZE: u32 = 4;
+#[allow(dead_code)]
+const CHECKER_COLORS: [[u8; 4]; 2] = [[204, 204, 204, 255], [153, 153, 153, 255]];
+
 struct World {
     right_held: bool,
     left_held: bool,
     background_image: DynamicImage,
     sprites: Vec<Sprite>,
+    debug_bounds: bool,
+
+    /// Sprites leaving one side of the world reappear on the other
+    wrap_x: bool,
+
+    /// How gradually held movement keys ramp the velocity, 0.0 snaps instantly
+    input_smoothing: f32,
+
+    /// Top-left corner of the visible part of the world
+    camera: (f32, f32),
+
+    /// Screen-space sprites drawn on top of the world, unaffected by the camera
+    ui_sprites: Vec<Sprite>,
+
+    /// Fixed number of physics steps per second, independent of the render rate
+    physics_hz: u32,
+
+    /// Elapsed time in seconds not yet consumed by a physics step
+    physics_accumulator: f64,
+
+    /// Number of smaller movements each physics step is split into, checking collision after each
+    physics_substeps: u32,
+
+    /// Most physics steps run to catch up before a frame is drawn, time past this is dropped
+    max_frame_skip: u32,
+
+    physics: PhysicsConfig,
+
+    /// Per-channel multiplier applied to the background, `[1.0, 1.0, 1.0]` leaves it untouched
+    background_tint: [f32; 3],
+
+    /// Expected time between rendered frames in seconds
+    target_frame_time: f64,
+
+    /// Number of frames that took too long compared to the target frame time
+    dropped_frames: u64,
+
+    /// Number of sprites skipped in the last draw because they were entirely off screen
+    culled_sprites: u32,
+
+    /// Orientation of world coordinates, used when drawing and for the floor
+    coordinate_system: CoordinateSystem,
+
+    /// File the background was loaded from and when it was last modified
+    background_path: PathBuf,
+    background_modified: Option<SystemTime>,
+
+    /// Reload textures automatically when their files change
+    hot_reload: bool,
+    last_reload_check: u128,
+
+    /// Nested clipping rectangles, drawing is limited to the last one
+    clip_stack: Vec<Rect>,
+
+    /// Mouse position in screen pixels, if it is over the window
+    mouse_position: Option<(f32, f32)>,
+
+    /// Sprite drawn on top of everything at the mouse position
+    cursor_sprite: Option<Sprite>,
+
+    /// A jump was requested and will happen on the next physics step
+    jump_requested: bool,
+
+    /// Number of physics steps run so far
+    frame_count: u64,
+
+    /// Open file that every physics step is appended to while recording
+    recording: Option<BufWriter<fs::File>>,
+
+    /// Check replayed physics against the state hashes stored in the recording
+    #[allow(dead_code)]
+    verify_replay: bool,
+
+    /// Speed of simulated time, 1.0 is real time, 0.5 slow motion and 0.0 frozen
+    time_scale: f32,
+
+    /// Scaled time animations are played against in milliseconds, and the real time it was last advanced
+    animation_clock: f64,
+    last_clock_update: Option<u128>,
+
+    /// Whether the animation clock follows real time or counts drawn frames
+    clock_mode: ClockMode,
+
+    /// In editor mode movement keys pan the camera instead of moving the player
+    editor_mode: bool,
+    editor_camera: EditorCamera,
+
+    /// Magnification of the world around the camera, UI is not affected
+    camera_zoom: f32,
+
+    /// Channel order the finished frame is written in
+    pixel_format: PixelFormat,
+
+    /// Freeze the physics simulation and sprite animations independently
+    physics_paused: bool,
+    animations_paused: bool,
+
+    /// Screen transition currently being drawn over everything
+    fade: Option<Fade>,
+
+    /// Events raised since the game last polled them
+    events: Vec<EngineEvent>,
+
+    /// Pairs of sprites that overlapped during the last physics step
+    contacts: Vec<(usize, usize)>,
+
+    /// Wall the player was touching at the end of the last physics step
+    wall_contact: Option<Side>,
+
+    /// Callbacks run when sprites matching both targets start overlapping
+    collision_callbacks: Vec<(CollisionTarget, CollisionTarget, CollisionCallback)>,
+
+    /// Most sprites that can be spawned into the world, unbounded if `None`
+    #[allow(dead_code)]
+    max_sprites: Option<usize>,
+
+    /// Zones that push any sprite overlapping them, like wind or conveyor belts
+    force_fields: Vec<ForceField>,
+
+    /// Visibility and opacity of sprite layers, layers not listed use the defaults
+    layers: HashMap<u8, Layer>,
+
+    /// How background texels are picked when the camera sits between pixels
+    background_sampling: BackgroundSampling,
+
+    /// Output pixels per world pixel, so the frame buffer can be sharper than the simulation.
+    /// Sheets authored at this resolution are drawn 1:1 by making their sprites with `Sprite::hi_res`.
+    render_scale: u32,
+
+    /// Sprite properties being animated, advanced every physics step
+    tweens: Vec<Tween>,
+
+    /// How ties between sprites with the same z index are broken when drawing
+    sort_policy: SortPolicy,
+
+    /// Paint the background once and copy it into later frames while nothing affecting it changes.
+    /// Only what sprites covered gets copied back, so every frame has to be drawn into the same buffer.
+    static_background: bool,
+    background_cache: Option<BackgroundCache>,
+
+    /// Copy of the last frame drawn by `draw_with_dirty`, to find what changed since
+    #[allow(dead_code)]
+    previous_frame: Option<Vec<u8>>,
+
+    /// Color of the bars around the world when it doesn't fill the window or target
+    letterbox_color: [u8; 4],
+
+    /// Recent action presses, tagged with the physics step they happened in
+    input_history: InputHistory,
+
+    /// What is drawn behind every sprite
+    background: Background,
+
+    /// Solid and sloped tiles the player can stand on, on top of the world's floor
+    collision_map: Option<CollisionMap>,
+
+    /// Area of the world the player is kept inside of, e.g. to keep them out from under the HUD
+    player_bounds: Option<Rect>,
+
+    /// How much of the previous frame is blended into each new one, 0 turns motion blur off
+    motion_blur: f32,
+    motion_blur_frame: Option<Vec<u8>>,
+
+    /// Camera shake currently playing, and how many seconds of simulation are still frozen by hit stop
+    screen_shake: Option<ScreenShake>,
+    hit_stop: f64,
+
+    /// Source of randomness for gameplay, seeded so runs can be repeated
+    #[allow(dead_code)]
+    rng: StdRng,
+
+    /// Regions of the output each drawn through their own camera, e.g. for split-screen.
+    /// When empty the whole frame is drawn from `camera`.
+    viewports: Vec<Viewport>,
+}
+
+/// A region of the output with its own camera
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Viewport {
+    /// Area of the frame in output pixels
+    rect: Rect,
+    /// World position drawn at the top-left of the frame, updated every draw while following a sprite
+    camera: (f32, f32),
+    /// Sprite kept centered in the viewport
+    follow_sprite: Option<usize>,
+}
+
+/// The source of the world's background
+enum Background {
+    /// The world's `background_image`, repeated as the camera scrolls
+    Image,
+    /// One animated tile repeated across the whole screen, e.g. water
+    #[allow(dead_code)]
+    AnimatedTile(SpriteSheet),
+}
+
+/// A painted background and the settings it was painted with
+struct BackgroundCache {
+    pixels: Vec<u8>,
+    camera: (f32, f32),
+    tint: [f32; 3],
+    sampling: BackgroundSampling,
+    render_scale: u32,
+    /// Everything drawn over the background in the last frame, `None` if the whole frame changed
+    overlay: Option<Rect>,
+}
+
+impl BackgroundCache {
+    /// Copy the cached background back over part of a frame `width` pixels wide
+    fn restore(&self, frame: &mut [u8], width:u32, rect:Rect) {
+        let height = (frame.len() / 4) as u32 / width.max(1);
+        let rect = rect.intersect(&Rect::new(0, 0, width, height));
+        for y in rect.y..rect.y + rect.height as i32 {
+            let start = (y as usize * width as usize + rect.x as usize) * 4;
+            let end = start + rect.width as usize * 4;
+            frame[start..end].copy_from_slice(&self.pixels[start..end]);
+        }
+    }
+}
+
+/// Filtering used when drawing the background
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BackgroundSampling {
+    /// Snap to whole texels, which can shimmer as a fractional camera scrolls
+    Nearest,
+    /// Blend the four texels around the sample point for smooth sub-pixel scrolling
+    #[allow(dead_code)]
+    Bilinear,
+}
+
+/// Edge or corner of the screen a UI sprite is placed relative to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(dead_code)]
+enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// Top-left corner of something `size` big placed `offset` in from this anchor of an area,
+    /// measured down from the top. Offsets from centered axes move right and down.
+    fn place(self, size:(f32, f32), offset:(f32, f32), area:(f32, f32)) -> (f32, f32) {
+        let (horizontal, vertical) = match self {
+            Anchor::TopLeft => (0, 0),
+            Anchor::Top => (1, 0),
+            Anchor::TopRight => (2, 0),
+            Anchor::Left => (0, 1),
+            Anchor::Center => (1, 1),
+            Anchor::Right => (2, 1),
+            Anchor::BottomLeft => (0, 2),
+            Anchor::Bottom => (1, 2),
+            Anchor::BottomRight => (2, 2),
+        };
+
+        let along = |alignment, size:f32, offset:f32, area:f32| match alignment {
+            0 => offset,
+            1 => (area - size) / 2.0 + offset,
+            _ => area - size - offset,
+        };
+
+        (along(horizontal, size.0, offset.0, area.0), along(vertical, size.1, offset.1, area.1))
+    }
+}
+
+/// Filtering used when a sprite's texture is scaled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SampleFilter {
+    /// Repeat whole texels, keeping hard pixel edges
+    Nearest,
+    /// Blend neighbouring texels for a smoother but softer result
+    #[allow(dead_code)]
+    Bilinear,
+}
+
+/// Order sprites with the same z index are drawn in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SortPolicy {
+    /// Earlier spawned sprites are drawn first, so later ones end up on top
+    SpawnOrder,
+    /// Later spawned sprites are drawn first, so earlier ones end up on top
+    ReverseSpawnOrder,
+}
+
+/// A sprite property a tween can animate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(dead_code)]
+enum TweenField {
+    PositionX,
+    PositionY,
+    ScaleX,
+    ScaleY,
+    Opacity,
+    Rotation,
+}
+
+/// How a tween's progress is shaped over its duration
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Easing {
+    Linear,
+    /// Starts and ends slowly, fastest in the middle
+    EaseInOut,
+}
+
+impl Easing {
+    /// Map linear progress from 0.0 to 1.0 onto the eased progress
+    fn apply(self, t:f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// Moves one property of a sprite from one value to another over time
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Tween {
+    sprite: usize,
+    target_field: TweenField,
+    from: f32,
+    to: f32,
+    /// Length of the tween and how much of it has played, in seconds
+    duration: f64,
+    elapsed: f64,
+    easing: Easing,
+}
+
+impl Tween {
+    #[allow(dead_code)]
+    fn new(sprite:usize, target_field:TweenField, from:f32, to:f32, duration:f64, easing:Easing) -> Self {
+        Self {
+            sprite,
+            target_field,
+            from,
+            to,
+            duration,
+            elapsed: 0.0,
+            easing,
+        }
+    }
+
+    /// The value of the property at the current point in the tween
+    fn value(&self) -> f32 {
+        let t = if self.duration > 0.0 { (self.elapsed / self.duration) as f32 } else { 1.0 };
+        self.from + (self.to - self.from) * self.easing.apply(t)
+    }
+
+    fn finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+/// Drawing settings shared by every sprite on a layer
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Layer {
+    visible: bool,
+    /// Multiplies the alpha of every sprite pixel, 0.0 to 1.0
+    opacity: f32,
+}
+
+impl Default for Layer {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            opacity: 1.0,
+        }
+    }
+}
+
+/// The collision shape of one tile in a `CollisionMap`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TileShape {
+    Empty,
+    /// Flat ground along the top of the tile
+    #[allow(dead_code)]
+    Solid,
+    /// 45° ramp rising towards the right, one pixel up for every pixel across
+    #[allow(dead_code)]
+    SlopeUp,
+    /// 45° ramp falling towards the right
+    #[allow(dead_code)]
+    SlopeDown,
+}
+
+/// A grid of collision tiles laid over the world from its top left corner, stored row by row
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct CollisionMap {
+    tile_size: u32,
+    width: usize,
+    tiles: Vec<TileShape>,
+}
+
+impl CollisionMap {
+    /// The tile at a grid position, empty outside of the map
+    fn tile(&self, column:usize, row:usize) -> TileShape {
+        if column >= self.width {
+            return TileShape::Empty;
+        }
+
+        self.tiles.get(row * self.width + column).copied().unwrap_or(TileShape::Empty)
+    }
+
+    /// The Y of the ground at world X, measured down from the top of the world, for feet at `feet_y`.
+    /// Ground up to a tile above the feet still counts so sprites can walk up slopes.
+    fn surface_below(&self, x:f32, feet_y:f32) -> Option<f32> {
+        if x < 0.0 || self.tile_size == 0 || self.width == 0 {
+            return None;
+        }
+
+        let size = self.tile_size as f32;
+        let column = (x / size) as usize;
+        let local_x = x - column as f32 * size;
+        let first_row = ((feet_y - size) / size).floor().max(0.0) as usize;
+        let rows = self.tiles.len().div_ceil(self.width);
+
+        for row in first_row..rows {
+            let top = row as f32 * size;
+            let surface = match self.tile(column, row) {
+                TileShape::Empty => continue,
+                TileShape::Solid => top,
+                TileShape::SlopeUp => top + size - local_x,
+                TileShape::SlopeDown => top + local_x,
+            };
+
+            if surface >= feet_y - size {
+                return Some(surface);
+            }
+        }
+
+        None
+    }
+}
+
+/// A region of the world that adds a constant force to overlapping sprites every physics step
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ForceField {
+    rect: Rect,
+    force: (f32, f32),
+}
+
+/// Why a sprite couldn't be added to the world
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(dead_code)]
+enum SpawnError {
+    /// The world already holds `max_sprites` sprites
+    AtCapacity(usize),
+    /// There was nothing to pick from, or the weights were negative or all zero
+    InvalidWeights,
+}
+
+impl std::fmt::Display for SpawnError {
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpawnError::AtCapacity(max_sprites) => write!(f, "world is at its limit of {max_sprites} sprites"),
+            SpawnError::InvalidWeights => write!(f, "no prototype could be picked from the given weights"),
+        }
+    }
+}
+
+impl std::error::Error for SpawnError {}
+
+/// No animation on the sprite's sheet has the requested name
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(dead_code)]
+struct AnimationNotFound(String);
+
+impl std::fmt::Display for AnimationNotFound {
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no animation named {:?}", self.0)
+    }
+}
+
+impl std::error::Error for AnimationNotFound {}
+
+/// Feedback gameplay asks for, routed by the engine to whatever can produce it. Durations are in seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(dead_code)]
+enum FeedbackEvent {
+    /// Controller vibration, shown as screen shake as no gamepad is supported
+    Rumble { intensity: f32, duration: f64 },
+    /// Shake the camera by up to `intensity` world pixels
+    ScreenShake { intensity: f32, duration: f64 },
+    /// Freeze the simulation briefly to make a hit land harder
+    HitStop { duration: f64 },
+}
+
+/// A camera shake that fades out over its duration
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ScreenShake {
+    intensity: f32,
+    duration: f64,
+    elapsed: f64,
+}
+
+impl ScreenShake {
+    /// How far the camera is pushed right now, jittering back and forth and shrinking to nothing
+    fn offset(&self) -> (f32, f32) {
+        let strength = self.offset_strength();
+        let phase = (self.elapsed * SCREEN_SHAKE_FREQUENCY) as f32 * std::f32::consts::TAU;
+
+        // Vertical runs at a different rate so the shake doesn't move along a straight line
+        (phase.sin() * strength, (phase * 1.7).cos() * strength)
+    }
+
+    /// The largest offset the shake can still reach
+    fn offset_strength(&self) -> f32 {
+        if self.duration <= 0.0 {
+            return 0.0;
+        }
+
+        self.intensity * (1.0 - self.elapsed / self.duration).clamp(0.0, 1.0) as f32
+    }
+}
+
+/// Something that happened inside the engine that game logic may want to react to.
+/// Events about a sprite that is despawned before they are polled are dropped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EngineEvent {
+    /// Two sprites started overlapping
+    Collision { a: usize, b: usize },
+    /// A sprite's `Once` animation played through
+    AnimationFinished { sprite: usize },
+    /// A sprite ran into an edge of the world
+    HitBoundary { sprite: usize, side: Side },
+}
+
+impl EngineEvent {
+    /// Move the event's sprite indices to where the sprites are after a removal,
+    /// returning false if any of them was removed
+    fn remap(&mut self, new_index:impl Fn(usize) -> Option<usize>) -> bool {
+        let indices = match self {
+            EngineEvent::Collision { a, b } => vec![a, b],
+            EngineEvent::AnimationFinished { sprite } | EngineEvent::HitBoundary { sprite, .. } => vec![sprite],
+        };
+
+        for index in indices {
+            match new_index(*index) {
+                Some(moved) => *index = moved,
+                None => return false,
+            }
+        }
+
+        true
+    }
+}
+
+/// What the animation clock advances with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ClockMode {
+    /// Real time, frame and fade durations are in milliseconds
+    WallClock,
+    /// One tick per drawn frame, frame and fade durations are in ticks
+    #[allow(dead_code)]
+    Ticks,
+}
+
+/// An edge of the world
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+/// Free-moving camera controls used by the level editor
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct EditorCamera {
+    up: bool,
+    down: bool,
+    left: bool,
+    right: bool,
+}
+
+impl EditorCamera {
+    /// Update the held state of a pan key, returning false if the key doesn't pan the camera
+    fn set_key(&mut self, key_id:VirtualKeyCode, held:bool) -> bool {
+        match key_id {
+            VirtualKeyCode::W | VirtualKeyCode::Up => self.up = held,
+            VirtualKeyCode::S | VirtualKeyCode::Down => self.down = held,
+            VirtualKeyCode::A | VirtualKeyCode::Left => self.left = held,
+            VirtualKeyCode::D | VirtualKeyCode::Right => self.right = held,
+            _ => return false,
+        }
+        true
+    }
+
+    /// The direction the camera is being panned in
+    fn direction(&self) -> (f32, f32) {
+        (
+            self.right as i8 as f32 - self.left as i8 as f32,
+            self.down as i8 as f32 - self.up as i8 as f32,
+        )
+    }
+}
+
+/// Tunable constants for the physics simulation
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PhysicsConfig {
+    /// Acceleration added to the velocity every step, with +Y pointing down the screen.
+    /// Only the larger part pulls, and sprites rest on whichever edge of the world it points at.
+    gravity: (f32, f32),
+    /// Slowdown per step across gravity while standing on the floor
+    ground_friction: f32,
+    /// Slowdown per step across gravity while in the air
+    air_friction: f32,
+    /// Check the whole movement of a step against the floor so fast falls land exactly on it
+    swept_floor: bool,
+    /// Fraction of the falling speed kept as upward speed when landing, 0.0 stops dead and 1.0 bounces forever
+    restitution: f32,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            gravity: (0.0, 0.1),
+            ground_friction: 0.1,
+            air_friction: 0.01,
+            swept_floor: true,
+            restitution: 0.0,
+        }
+    }
 }
 
+/// Whether a fade reveals the scene or covers it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FadeDirection {
+    /// From the fade color to the scene
+    In,
+    /// From the scene to the fade color
+    #[allow(dead_code)]
+    Out,
+}
+
+/// A full-screen color overlay whose opacity changes over time
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Fade {
+    color: [u8; 3],
+    duration: u64,
+    direction: FadeDirection,
+    start_time: f64,
+}
+
+impl Fade {
+    /// Opacity of the overlay at `time` milliseconds, from 0.0 to 1.0
+    fn alpha(&self, time:f64) -> f32 {
+        let progress = if self.duration == 0 {
+            1.0
+        } else {
+            ((time - self.start_time) / self.duration as f64).clamp(0.0, 1.0) as f32
+        };
+
+        match self.direction {
+            FadeDirection::In => 1.0 - progress,
+            FadeDirection::Out => progress,
+        }
+    }
+}
+
+/// Channel order of the frame buffer handed to `draw`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PixelFormat {
+    Rgba8,
+    Bgra8,
+}
+
+/// Where the world origin is and which way Y points
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CoordinateSystem {
+    TopLeftYDown,
+    #[allow(dead_code)]
+    BottomLeftYUp,
+}
+
+/// A rectangle in world pixel coordinates
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Rect {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+impl Rect {
+    fn new(x:i32, y:i32, width:u32, height:u32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    /// Whether a pixel lies inside the rectangle
+    fn contains(&self, x:i32, y:i32) -> bool {
+        x >= self.x
+            && y >= self.y
+            && (x as i64) < self.x as i64 + self.width as i64
+            && (y as i64) < self.y as i64 + self.height as i64
+    }
+
+    /// The overlapping area of two rectangles, empty if they don't overlap
+    fn intersect(&self, other:&Rect) -> Rect {
+        let left = self.x.max(other.x);
+        let top = self.y.max(other.y);
+        let right = (self.x as i64 + self.width as i64).min(other.x as i64 + other.width as i64);
+        let bottom = (self.y as i64 + self.height as i64).min(other.y as i64 + other.height as i64);
+
+        Rect::new(
+            left,
+            top,
+            (right - left as i64).max(0) as u32,
+            (bottom - top as i64).max(0) as u32,
+        )
+    }
+
+    /// The smallest rectangle holding both, ignoring either one if it's empty
+    fn union(&self, other:&Rect) -> Rect {
+        if other.width == 0 || other.height == 0 {
+            return *self;
+        }
+        if self.width == 0 || self.height == 0 {
+            return *other;
+        }
+
+        let left = self.x.min(other.x);
+        let top = self.y.min(other.y);
+        let right = (self.x as i64 + self.width as i64).max(other.x as i64 + other.width as i64);
+        let bottom = (self.y as i64 + self.height as i64).max(other.y as i64 + other.height as i64);
+
+        Rect::new(left, top, (right - left as i64) as u32, (bottom - top as i64) as u32)
+    }
+}
+
+/// A template for spawning many identical sprites that share one texture
+#[derive(Clone)]
+#[allow(dead_code)]
+struct SpritePrototype {
+    sprite_sheet: SpriteSheet,
+    velocity: (f32, f32),
+    angular_velocity: f32,
+    layer: u8,
+    z_index: i32,
+    despawn_offscreen: bool,
+}
+
+impl SpritePrototype {
+    #[allow(dead_code)]
+    fn new(sprite_sheet:SpriteSheet) -> Self {
+        Self {
+            sprite_sheet,
+            velocity: (0.0, 0.0),
+            angular_velocity: 0.0,
+            layer: 0,
+            z_index: 0,
+            despawn_offscreen: false,
+        }
+    }
+
+    /// Create a sprite configured like the prototype at a world position
+    #[allow(dead_code)]
+    fn instantiate(&self, position:(f32, f32)) -> Sprite {
+        let mut sprite = Sprite::new(self.sprite_sheet.clone());
+        sprite.position = position;
+        sprite.velocity = self.velocity;
+        sprite.angular_velocity = self.angular_velocity;
+        sprite.layer = self.layer;
+        sprite.z_index = self.z_index;
+        sprite.despawn_offscreen = self.despawn_offscreen;
+        sprite
+    }
+}
 
 /// The basic sprite struct, is used to draw an object to the output
 struct Sprite {
@@ -29,409 +864,6062 @@ struct Sprite {
     position: (f32, f32),
     velocity: (f32, f32),
     sprite_sheet: SpriteSheet,
-}
 
-/// Sprite sheet, stores the different looks of a sprite
-struct SpriteSheet {
-    texture: DynamicImage,
-    frame_size: (u16, u16),
-    animations: Vec<Animation>,
-    current_animation: usize,
-    sheet_dimensions: (u16, u16),
-}
+    /// Horizontal and vertical draw scale
+    scale: (f32, f32),
 
-/// Animations for a sprite sheet
-struct Animation {
-    starting_frame_position: (u16, u16),
-    num_frames: u16,
-    frame_duration: u64,
-    current_frame: u16,
-    current_position: (u16, u16),
-    previous_frame_time: i128,
-}
+    /// Keep the horizontal and vertical scale equal
+    #[allow(dead_code)]
+    lock_aspect: bool,
+
+    /// Clockwise rotation in radians
+    rotation: f32,
+
+    /// How fast the rotation changes, in radians per second
+    angular_velocity: f32,
+
+    /// Point the sprite rotates around, as a fraction of its scaled size
+    anchor: (f32, f32),
+
+    /// Remove the sprite from the world once its animation finishes. Later sprites move down an index.
+    despawn_on_finish: bool,
+
+    /// Remove the sprite from the world once it's entirely outside of it, e.g. projectiles.
+    /// Does nothing while the world wraps around, as sprites can't leave it then.
+    despawn_offscreen: bool,
+
+    /// Automatically face the direction of horizontal movement
+    auto_face: bool,
+
+    /// Index of the sprite this one follows, if any
+    parent: Option<usize>,
+
+    /// Offset from the parent's position, mirrored when the parent faces left
+    local_offset: (f32, f32),
+
+    /// Custom logic run every physics step
+    behavior: Option<Behavior>,
+
+    /// Color of a 1px outline drawn around the visible pixels, if any
+    outline: Option<[u8; 4]>,
+
+    /// Draw a soft shadow on the floor beneath the sprite
+    cast_shadow: bool,
+
+    /// Group the sprite belongs to, for hiding or fading many sprites at once
+    layer: u8,
+
+    /// Multiplies the alpha of every pixel, 0.0 to 1.0
+    opacity: f32,
+
+    /// Sprites with a higher z index are drawn on top
+    z_index: i32,
+
+    /// How the texture is sampled when the sprite is scaled
+    filter: SampleFilter,
+
+    /// Pin the sprite above every unpinned sprite, pinned sprites draw in increasing priority
+    draw_priority: Option<u32>,
+
+    /// Switch to `idle_animation` after this long without movement input, only used by the player
+    idle_timeout_ms: Option<u64>,
+    idle_animation: usize,
+
+    /// Time since the last movement input, and the animation to return to once movement resumes
+    idle_elapsed_ms: f64,
+    animation_before_idle: Option<usize>,
+
+    /// Screen edge a UI sprite is placed against, its position then being the offset in from that edge
+    screen_anchor: Option<Anchor>,
+
+    /// Sprites with a lower priority are updated first, e.g. parents before their children
+    update_priority: i32,
+
+    /// Draw the sprite on whole world pixels even when `render_scale` could place it between them
+    pixel_snap: bool,
+
+    /// Hold the current animation frame until `unfreeze` is called
+    frozen: bool,
+}
+
+/// Custom per-step logic attached to a sprite
+type Behavior = Box<dyn FnMut(&mut Sprite, &WorldContext)>;
+
+/// Called with the indices of two sprites that just started overlapping, in the order they were registered
+type CollisionCallback = Box<dyn FnMut(usize, usize)>;
+
+/// One side of a registered collision callback
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CollisionTarget {
+    /// A single sprite by index
+    #[allow(dead_code)]
+    Sprite(usize),
+    /// Any sprite on a draw layer
+    #[allow(dead_code)]
+    Layer(u8),
+}
+
+impl CollisionTarget {
+    /// Move a sprite target to where its sprite is after a removal, returning false if it was removed
+    fn remap(&mut self, new_index:impl Fn(usize) -> Option<usize>) -> bool {
+        match self {
+            CollisionTarget::Sprite(index) => match new_index(*index) {
+                Some(moved) => {
+                    *index = moved;
+                    true
+                }
+                None => false,
+            },
+            CollisionTarget::Layer(_) => true,
+        }
+    }
+
+    fn matches(self, index:usize, sprite:&Sprite) -> bool {
+        match self {
+            CollisionTarget::Sprite(target) => target == index,
+            CollisionTarget::Layer(layer) => layer == sprite.layer,
+        }
+    }
+}
+
+/// Read-only view of the world handed to sprite behaviors
+#[allow(dead_code)]
+struct WorldContext {
+    /// State of every sprite at the start of the step
+    sprites: Vec<SpriteState>,
+    /// Current time in milliseconds
+    time: u128,
+    /// Length of the physics step in seconds
+    dt: f64,
+}
+
+/// Sprite sheet, stores the different looks of a sprite. Clones share the texture.
+#[derive(Clone)]
+struct SpriteSheet {
+    texture: Rc<DynamicImage>,
+    frame_size: (u16, u16),
+    animations: Vec<Animation>,
+    current_animation: usize,
+    sheet_dimensions: (u16, u16),
+
+    /// File the texture was loaded from, if any, and when it was last modified
+    texture_path: Option<PathBuf>,
+    texture_modified: Option<SystemTime>,
+
+    /// The texture's colors are already multiplied by their alpha
+    premultiplied: bool,
+
+    /// Empty gutter between neighbouring frames on the sheet
+    frame_spacing: (u16, u16),
+
+    /// How the texture's alpha channel is applied when drawing
+    alpha_mode: AlphaMode,
+}
+
+/// How transparent sprite pixels are drawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AlphaMode {
+    /// Blend partially transparent pixels with what's underneath
+    Blend,
+    /// Skip pixels with alpha below the threshold and copy the rest over as fully opaque
+    #[allow(dead_code)]
+    Clip(u8),
+}
+
+/// Animations for a sprite sheet
+#[derive(Clone)]
+struct Animation {
+    /// Name used to look the animation up with `Sprite::play`, may be empty
+    name: String,
+    starting_frame_position: (u16, u16),
+    num_frames: u16,
+    frame_duration: u64,
+    current_frame: u16,
+    current_position: (u16, u16),
+    previous_frame_time: i128,
+
+    /// Clock time the animation was last updated at
+    last_update_time: i128,
+
+    /// Explicit frame rectangles for packed sheets, empty when frames are laid out on a grid
+    frames: Vec<Frame>,
+
+    /// What happens after the last frame, and whether a `Once` animation has completed
+    loop_mode: LoopMode,
+    finished: bool,
+
+    /// Order grid frames are read from the sheet in
+    layout: AnimationLayout,
+}
+
+/// Direction frames on a grid sheet advance in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AnimationLayout {
+    /// Left to right, then down to the next row
+    RowMajor,
+    /// Top to bottom, then right to the next column
+    #[allow(dead_code)]
+    ColumnMajor,
+}
+
+/// Whether an animation repeats or stops after playing through
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LoopMode {
+    Loop,
+    Once,
+}
+
+/// A single frame on a packed sprite sheet
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Frame {
+    position: (u16, u16),
+    size: (u16, u16),
+
+    /// How long this frame is shown in milliseconds, overriding the animation's frame duration
+    duration: Option<u64>,
+}
+
+/// Top level RON manifest listing everything needed to build a world.
+/// Paths are relative to the manifest file.
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct WorldManifest {
+    background: PathBuf,
+    /// Sprite manifests, one per sprite
+    sprites: Vec<PathBuf>,
+}
+
+/// RON manifest describing a single sprite and its sheet
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct SpriteManifest {
+    texture: PathBuf,
+    frame_size: (u16, u16),
+    animations: Vec<AnimationManifest>,
+    #[serde(default)]
+    position: (f32, f32),
+}
+
+/// A row of frames on a sprite sheet grid
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct AnimationManifest {
+    #[serde(default)]
+    name: String,
+    row: u16,
+    #[serde(default)]
+    start_col: u16,
+    frames: u16,
+    frame_duration: u64,
+}
+
+/// Why loading an asset failed, along with the file that caused it
+#[derive(Debug)]
+#[allow(dead_code)]
+enum LoadError {
+    Io { path: PathBuf, source: std::io::Error },
+    Parse { path: PathBuf, message: String },
+    Image { path: PathBuf, source: ImageError },
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io { path, source } => write!(f, "failed to read {}: {source}", path.display()),
+            LoadError::Parse { path, message } => write!(f, "failed to parse {}: {message}", path.display()),
+            LoadError::Image { path, source } => write!(f, "failed to load image {}: {source}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io { source, .. } => Some(source),
+            LoadError::Parse { .. } => None,
+            LoadError::Image { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Read and parse a RON manifest
+#[allow(dead_code)]
+fn read_manifest<T:serde::de::DeserializeOwned>(path:&Path) -> Result<T, LoadError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|source| LoadError::Io { path: path.to_path_buf(), source })?;
+    ron::from_str(&contents)
+        .map_err(|err| LoadError::Parse { path: path.to_path_buf(), message: err.to_string() })
+}
+
+/// The parts of an Aseprite JSON export used to build animations
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct AsepriteAtlas {
+    frames: AsepriteFrames,
+    meta: AsepriteMeta,
+}
+
+/// Aseprite exports frames either as an array or as an object keyed by file name
+#[derive(Deserialize)]
+#[serde(untagged)]
+#[allow(dead_code)]
+enum AsepriteFrames {
+    Array(Vec<AsepriteFrame>),
+    Hash(serde_json::Map<String, serde_json::Value>),
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct AsepriteFrame {
+    frame: AsepriteRect,
+    duration: u64,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct AsepriteRect {
+    x: u16,
+    y: u16,
+    w: u16,
+    h: u16,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct AsepriteMeta {
+    #[serde(rename = "frameTags", default)]
+    frame_tags: Vec<AsepriteTag>,
+}
+
+/// A named range of frames, which becomes one animation
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct AsepriteTag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: String,
+}
+
+/// Snapshot of the mutable state of a `World`, textures are not included
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct WorldState {
+    camera: (f32, f32),
+    sprites: Vec<SpriteState>,
+}
+
+/// Snapshot of the mutable state of a single `Sprite`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct SpriteState {
+    position: (f32, f32),
+    velocity: (f32, f32),
+    facing_left: bool,
+    current_animation: usize,
+    current_frame: u16,
+    current_position: (u16, u16),
+}
+
+/// Player input that drives a single physics step
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct InputState {
+    left: bool,
+    right: bool,
+    jump: bool,
+}
+
+/// One recorded physics step, the input fed into it and the resulting sprite positions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct FrameRecord {
+    frame: u64,
+    input: InputState,
+    positions: Vec<(f32, f32)>,
+    /// Hash of every sprite's position and velocity, missing in older recordings
+    #[serde(default)]
+    state_hash: Option<u64>,
+}
+
+/// Timing of a headless benchmark run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(dead_code)]
+struct BenchReport {
+    frames: usize,
+    /// Time spent across every frame
+    total: Duration,
+    /// Mean and slowest time for a single step and render
+    avg: Duration,
+    max: Duration,
+}
+
+/// A texture held by the world, for tracking down memory use
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(dead_code)]
+struct AssetInfo {
+    /// File the texture was loaded from, `None` for textures built in memory
+    path: Option<PathBuf>,
+    width: u32,
+    height: u32,
+    /// Estimated size of the decoded texture as RGBA
+    bytes: u64,
+}
+
+impl AssetInfo {
+    #[allow(dead_code)]
+    fn new(path:Option<PathBuf>, texture:&DynamicImage) -> Self {
+        let (width, height) = texture.dimensions();
+        Self {
+            path,
+            width,
+            height,
+            bytes: width as u64 * height as u64 * 4,
+        }
+    }
+}
+
+/// Something the player can do, bound to one or more keys
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Action {
+    Left,
+    Right,
+    Up,
+    Down,
+    Jump,
+    Pause,
+    ToggleDebug,
+    ToggleEditor,
+    /// Cycle the player's animations, only in debug builds
+    NextAnimation,
+    PrevAnimation,
+    Screenshot,
+}
+
+/// The most recent action presses, oldest first, for detecting combos
+#[derive(Clone, Debug, Default, PartialEq)]
+struct InputHistory {
+    presses: VecDeque<(Action, u64)>,
+}
+
+impl InputHistory {
+    /// Remember an action pressed during `frame`, forgetting the oldest press once full
+    fn push(&mut self, action:Action, frame:u64) {
+        if self.presses.len() == INPUT_HISTORY_LEN {
+            self.presses.pop_front();
+        }
+        self.presses.push_back((action, frame));
+    }
+
+    /// Whether `sequence` was pressed in order, ending with the latest press, with every press of it
+    /// in the `window_frames` frames up to `current_frame`. Other presses in between are allowed.
+    #[allow(dead_code)]
+    fn matches_sequence(&self, sequence:&[Action], window_frames:u64, current_frame:u64) -> bool {
+        let Some(&(latest, _)) = self.presses.back() else {
+            return sequence.is_empty();
+        };
+        if sequence.last().is_some_and(|&last| last != latest) {
+            return false;
+        }
+
+        // Walk back through the history matching the sequence from its end
+        let oldest_frame = current_frame.saturating_sub(window_frames);
+        let mut remaining = sequence.iter().rev().peekable();
+        for &(action, frame) in self.presses.iter().rev() {
+            if frame < oldest_frame {
+                break;
+            }
+            if remaining.peek() == Some(&&action) {
+                remaining.next();
+            }
+        }
+        remaining.peek().is_none()
+    }
+}
+
+/// Key bindings for every action, usually loaded from `controls.ron`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct InputMap {
+    bindings: HashMap<Action, Vec<VirtualKeyCode>>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        Self {
+            bindings: HashMap::from([
+                (Action::Left, vec![VirtualKeyCode::Left, VirtualKeyCode::A]),
+                (Action::Right, vec![VirtualKeyCode::Right, VirtualKeyCode::D]),
+                (Action::Up, vec![VirtualKeyCode::Up, VirtualKeyCode::W]),
+                (Action::Down, vec![VirtualKeyCode::Down, VirtualKeyCode::S]),
+                (Action::Jump, vec![VirtualKeyCode::Up]),
+                (Action::Pause, vec![VirtualKeyCode::P]),
+                (Action::ToggleDebug, vec![VirtualKeyCode::F3]),
+                (Action::ToggleEditor, vec![VirtualKeyCode::F2]),
+                (Action::NextAnimation, vec![VirtualKeyCode::RBracket]),
+                (Action::PrevAnimation, vec![VirtualKeyCode::LBracket]),
+                (Action::Screenshot, vec![VirtualKeyCode::F12]),
+            ]),
+        }
+    }
+}
+
+impl InputMap {
+    /// Load bindings from a RON file, using the defaults if it doesn't exist.
+    /// Actions missing from the file keep their default keys.
+    fn from_file(path:&Path) -> Result<Self, Box<dyn std::error::Error>> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let mut map:InputMap = ron::from_str(&fs::read_to_string(path)?)
+            .map_err(|err| format!("invalid controls file {}: {err}", path.display()))?;
+        for (action, keys) in Self::default().bindings {
+            map.bindings.entry(action).or_insert(keys);
+        }
+        Ok(map)
+    }
+
+    /// The keys bound to an action
+    fn keys(&self, action:Action) -> &[VirtualKeyCode] {
+        self.bindings.get(&action).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Whether any key bound to the action was pressed this update
+    fn pressed(&self, input:&WinitInputHelper, action:Action) -> bool {
+        self.keys(action).iter().any(|&key| input.key_pressed(key))
+    }
+
+    /// Every action with a bound key pressed this update
+    fn pressed_actions<'a>(&'a self, input:&'a WinitInputHelper) -> impl Iterator<Item = Action> + 'a {
+        self.bindings.keys().copied().filter(|&action| self.pressed(input, action))
+    }
+
+    /// Whether any key bound to the action was released this update
+    fn released(&self, input:&WinitInputHelper, action:Action) -> bool {
+        self.keys(action).iter().any(|&key| input.key_released(key))
+    }
+}
+
+/// A presentation layer that `World` can draw frames into
+trait RenderTarget {
+    /// The RGBA frame buffer to draw into
+    fn frame_mut(&mut self) -> &mut [u8];
+
+    /// Show the contents of the frame buffer
+    fn present(&mut self) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+impl RenderTarget for Pixels {
+    fn frame_mut(&mut self) -> &mut [u8] {
+        self.get_frame_mut()
+    }
+
+    fn present(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.render()?;
+        Ok(())
+    }
+}
+
+/// A plain in-memory buffer, useful for headless rendering
+impl RenderTarget for Vec<u8> {
+    fn frame_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+
+    fn present(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+}
+
+/// Size of the window in logical pixels when each world pixel covers `scale` window pixels
+fn window_logical_size(scale:u32) -> LogicalSize<f64> {
+    LogicalSize::new((WORLD_WIDTH * scale) as f64, (WORLD_HEIGHT * scale) as f64)
+}
+
+/// The largest whole number scale, at least 1, that fits the world in the window.
+/// `pixels` centers the scaled frame itself and fills the bars around it with the letterbox color.
+fn integer_scale(window_size:(u32, u32), world_size:(u32, u32)) -> u32 {
+    (window_size.0 / world_size.0.max(1))
+        .min(window_size.1 / world_size.1.max(1))
+        .max(1)
+}
+
+/// Convert a logical size to physical pixels for a display's scale factor
+fn physical_size(size:LogicalSize<f64>, scale_factor:f64) -> PhysicalSize<u32> {
+    size.to_physical(scale_factor)
+}
+
+/// Scale a color's alpha by `opacity`, and its color channels too if they are premultiplied
+fn fade_color(color:[u8; 4], opacity:f32, premultiplied:bool) -> [u8; 4] {
+    let fade = |channel:u8| (channel as f32 * opacity) as u8;
+    if premultiplied {
+        color.map(fade)
+    } else {
+        [color[0], color[1], color[2], fade(color[3])]
+    }
+}
+
+/// The last modification time of a file, if it can be read
+fn modified_time(path:&Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// One part of an (x, y) pair, 0 for X and 1 for Y
+fn component(pair:(f32, f32), axis:usize) -> f32 {
+    if axis == 0 { pair.0 } else { pair.1 }
+}
+
+/// One part of an (x, y) pair to change in place, 0 for X and 1 for Y
+fn component_mut(pair:&mut (f32, f32), axis:usize) -> &mut f32 {
+    if axis == 0 { &mut pair.0 } else { &mut pair.1 }
+}
+
+fn get_current_time() -> u128 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(n) => n.as_millis(),
+        Err(_) => panic!("SystemTime before UNIX EPOCH!"),
+    }
+}
+
+impl Sprite {
+    fn new(sprite_sheet:SpriteSheet) -> Self {
+        Self {
+            size: sprite_sheet.frame_size,
+            sprite_sheet,
+            facing_left: false,
+
+            // Physics information
+            // TODO: move this to its own struct
+            position: (0.0, 0.0),
+            velocity: (0.0, 0.0),
+
+            scale: (1.0, 1.0),
+            lock_aspect: false,
+
+            rotation: 0.0,
+            angular_velocity: 0.0,
+            anchor: (0.5, 0.5),
+
+            despawn_on_finish: false,
+            despawn_offscreen: false,
+            auto_face: false,
+
+            parent: None,
+            local_offset: (0.0, 0.0),
+
+            behavior: None,
+            outline: None,
+            cast_shadow: false,
+            layer: 0,
+            opacity: 1.0,
+            z_index: 0,
+            draw_priority: None,
+            filter: SampleFilter::Nearest,
+            idle_timeout_ms: None,
+            idle_animation: 0,
+            idle_elapsed_ms: 0.0,
+            animation_before_idle: None,
+            screen_anchor: None,
+            update_priority: 0,
+            pixel_snap: false,
+            frozen: false,
+        }
+    }
+
+    /// Shrink a sprite whose sheet has `density` texels per world pixel down to its size in the world,
+    /// so the floor and walls stop what's drawn and a render scale of `density` shows every texel 1:1
+    #[allow(dead_code)]
+    fn hi_res(mut self, density:u16) -> Self {
+        let density = density.max(1);
+        self.scale = (1.0 / density as f32, 1.0 / density as f32);
+        self.size = (self.size.0 / density, self.size.1 / density);
+        self
+    }
+
+    /// Attach custom logic to run on this sprite every physics step
+    #[allow(dead_code)]
+    fn set_behavior(&mut self, behavior:impl FnMut(&mut Sprite, &WorldContext) + 'static) {
+        self.behavior = Some(Box::new(behavior));
+    }
+
+    /// Whether the current animation has played through and stopped
+    fn animation_finished(&self) -> bool {
+        let current = self.sprite_sheet.current_animation;
+        self.sprite_sheet.animations[current].finished
+    }
+
+    /// Whether the sprite asked to be despawned and is done, its one-shot animation finished
+    /// or it's completely outside the world and its margin, which it never is in a wrapping world
+    fn should_despawn(&self, wrap_x:bool) -> bool {
+        if self.despawn_on_finish && self.animation_finished() {
+            return true;
+        }
+
+        let margin = OFFSCREEN_DESPAWN_MARGIN;
+        let (min_x, min_y, max_x, max_y) = self.rendered_aabb();
+        self.despawn_offscreen && !wrap_x && (
+            max_x < -margin
+                || max_y < -margin
+                || min_x > WORLD_WIDTH as f32 + margin
+                || min_y > WORLD_HEIGHT as f32 + margin
+        )
+    }
+
+    /// Make this sprite follow another sprite at the given offset
+    #[allow(dead_code)]
+    fn attach_to(&mut self, parent:usize, offset:(f32, f32)) {
+        self.parent = Some(parent);
+        self.local_offset = offset;
+    }
+
+    /// Stop following the parent sprite, keeping the current position
+    #[allow(dead_code)]
+    fn detach(&mut self) {
+        self.parent = None;
+    }
+
+    /// Set the draw scale, using the larger axis for both if the aspect ratio is locked
+    #[allow(dead_code)]
+    fn set_scale(&mut self, x:f32, y:f32) {
+        if self.lock_aspect {
+            let uniform = x.max(y);
+            self.scale = (uniform, uniform);
+        } else {
+            self.scale = (x, y);
+        }
+    }
+
+    /// Scale both axes by the same amount
+    #[allow(dead_code)]
+    fn uniform_scale(&mut self, factor:f32) {
+        self.scale = (factor, factor);
+    }
+
+    /// The size of the current animation frame in texels, which may differ from `size` on packed or hi-res sheets
+    fn frame_size(&self) -> (u16, u16) {
+        let current = self.sprite_sheet.current_animation;
+        self.sprite_sheet.animations[current].current_frame_size().unwrap_or(self.sprite_sheet.frame_size)
+    }
+
+    /// The size of the sprite in world pixels after scaling
+    fn scaled_size(&self) -> (u16, u16) {
+        self.scaled_size_at(1.0)
+    }
+
+    /// The size of the sprite after scaling, drawn with `render_scale` output pixels per world pixel
+    fn scaled_size_at(&self, render_scale:f32) -> (u16, u16) {
+        let size = self.frame_size();
+        (
+            (size.0 as f32 * self.scale.0.max(0.0) * render_scale).round() as u16,
+            (size.1 as f32 * self.scale.1.max(0.0) * render_scale).round() as u16,
+        )
+    }
+
+    fn collision_y(&self) -> bool {
+        self.position.1 as u16 + self.size.1 >= WORLD_HEIGHT as u16
+    }
+
+    /// The anchor in pixels at a render scale, measured from the top left of the scaled sprite
+    fn pivot_at(&self, render_scale:f32) -> (f32, f32) {
+        let (width, height) = self.scaled_size_at(render_scale);
+        (self.anchor.0 * width as f32, self.anchor.1 * height as f32)
+    }
+
+    /// Bounds of the scaled and rotated sprite in world pixels as `(min_x, min_y, max_x, max_y)`,
+    /// relative to the top left of the unrotated sprite
+    fn local_aabb(&self) -> (f32, f32, f32, f32) {
+        self.local_aabb_at(1.0)
+    }
+
+    /// Same as `local_aabb`, measured in output pixels at a render scale
+    fn local_aabb_at(&self, render_scale:f32) -> (f32, f32, f32, f32) {
+        let (width, height) = self.scaled_size_at(render_scale);
+        let (width, height) = (width as f32, height as f32);
+        if self.rotation == 0.0 {
+            return (0.0, 0.0, width, height);
+        }
+
+        let pivot = self.pivot_at(render_scale);
+        let (sin, cos) = self.rotation.sin_cos();
+        let mut aabb = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+        for (x, y) in [(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)] {
+            // Rotate each corner around the pivot
+            let (dx, dy) = (x - pivot.0, y - pivot.1);
+            let corner = (pivot.0 + dx * cos - dy * sin, pivot.1 + dx * sin + dy * cos);
+            aabb = (aabb.0.min(corner.0), aabb.1.min(corner.1), aabb.2.max(corner.0), aabb.3.max(corner.1));
+        }
+        aabb
+    }
+
+    /// A lookup from an output pixel relative to the top left of the upright, scaled sprite to the
+    /// visible texture color drawn there, if any, with `render_scale` output pixels per world pixel
+    fn sampler(&self, render_scale:f32) -> impl Fn(i32, i32) -> Option<Rgba<u8>> + '_ {
+        let offset = self.get_sheet_offset();
+        let frame_size = self.frame_size();
+        let (width, height) = self.scaled_size_at(render_scale);
+        let pivot = self.pivot_at(render_scale);
+        let draw_scale = (self.scale.0 * render_scale, self.scale.1 * render_scale);
+        let (sin, cos) = self.rotation.sin_cos();
+        let texture = self.get_sprite_sheet();
+
+        move |x, y| {
+            if frame_size.0 == 0 || frame_size.1 == 0 {
+                return None;
+            }
+
+            // Undo the rotation around the pivot to find this pixel on the upright sprite
+            let (dx, dy) = (x as f32 + 0.5 - pivot.0, y as f32 + 0.5 - pivot.1);
+            let local_x = pivot.0 + dx * cos + dy * sin;
+            let local_y = pivot.1 - dx * sin + dy * cos;
+            if local_x < 0.0 || local_y < 0.0 || local_x >= width as f32 || local_y >= height as f32 {
+                return None;
+            }
+
+            // Look up a pixel of the unscaled frame, mirrored if the sprite faces left
+            let texel = |source_x:u16, source_y:u16| {
+                let x_coord = if self.facing_left() {
+                    frame_size.0 - 1 - source_x
+                } else {
+                    source_x
+                };
+
+                let (sample_x, sample_y) = ((x_coord + offset.0) as u32, (source_y + offset.1) as u32);
+                if sample_x >= texture.width() || sample_y >= texture.height() {
+                    return Rgba([0, 0, 0, 0]);
+                }
+                texture.get_pixel(sample_x, sample_y)
+            };
+
+            // Find the point of the unscaled frame that lands here
+            let (source_x, source_y) = (local_x / draw_scale.0, local_y / draw_scale.1);
+            let colors = match self.filter {
+                SampleFilter::Nearest => texel(
+                    (source_x as u16).min(frame_size.0 - 1),
+                    (source_y as u16).min(frame_size.1 - 1),
+                ),
+                SampleFilter::Bilinear => {
+                    // Blend the four texel centers around the point, clamped to the frame's edges
+                    let (center_x, center_y) = ((source_x - 0.5).max(0.0), (source_y - 0.5).max(0.0));
+                    let left = (center_x as u16).min(frame_size.0 - 1);
+                    let top = (center_y as u16).min(frame_size.1 - 1);
+                    let right = (left + 1).min(frame_size.0 - 1);
+                    let bottom = (top + 1).min(frame_size.1 - 1);
+                    let (fraction_x, fraction_y) = (center_x.fract(), center_y.fract());
+
+                    let (top_left, top_right) = (texel(left, top), texel(right, top));
+                    let (bottom_left, bottom_right) = (texel(left, bottom), texel(right, bottom));
+                    let mut rgba = [0; 4];
+                    for (c, channel) in rgba.iter_mut().enumerate() {
+                        let upper = top_left[c] as f32 + (top_right[c] as f32 - top_left[c] as f32) * fraction_x;
+                        let lower = bottom_left[c] as f32 + (bottom_right[c] as f32 - bottom_left[c] as f32) * fraction_x;
+                        *channel = (upper + (lower - upper) * fraction_y).round() as u8;
+                    }
+                    Rgba(rgba)
+                }
+            };
+
+            let visible = match self.sprite_sheet.alpha_mode {
+                AlphaMode::Blend => colors[3] > 0,
+                AlphaMode::Clip(threshold) => colors[3] > 0 && colors[3] >= threshold,
+            };
+            visible.then_some(colors)
+        }
+    }
+
+    /// Bounds of the sprite as drawn, after scale, rotation and anchor, as `(min_x, min_y, max_x, max_y)`
+    fn rendered_aabb(&self) -> (f32, f32, f32, f32) {
+        let (min_x, min_y, max_x, max_y) = self.local_aabb();
+        (
+            self.position.0 + min_x,
+            self.position.1 + min_y,
+            self.position.0 + max_x,
+            self.position.1 + max_y,
+        )
+    }
+
+    /// The axis-aligned bounding box of the sprite in world pixels
+    fn bounds(&self) -> Rect {
+        let (min_x, min_y, max_x, max_y) = self.rendered_aabb();
+        Rect::new(
+            min_x.floor() as i32,
+            min_y.floor() as i32,
+            (max_x.ceil() - min_x.floor()) as u32,
+            (max_y.ceil() - min_y.floor()) as u32,
+        )
+    }
+
+    fn run_animation(&mut self, current_time:i128) {
+        let frame_size = self.sprite_sheet.frame_size;
+        let frame_spacing = self.sprite_sheet.frame_spacing;
+        let sheet_dimensions = self.sprite_sheet.sheet_dimensions;
+        let current = self.sprite_sheet.current_animation;
+        let animation = &mut self.sprite_sheet.animations[current];
+
+        // Keep restarting the frame's timer so it plays in full once unfrozen
+        if self.frozen {
+            animation.previous_frame_time = current_time;
+            animation.last_update_time = current_time;
+            return;
+        }
+
+        animation.increment_frame(frame_size, frame_spacing, sheet_dimensions, current_time);
+    }
+
+    /// Show a frame of the current animation and hold it there until `unfreeze` is called
+    #[allow(dead_code)]
+    fn freeze_frame(&mut self, index:u16) {
+        let num_frames = self.sprite_sheet.animations[self.sprite_sheet.current_animation].num_frames;
+        self.restart_animation();
+        self.advance_frames(index.min(num_frames.saturating_sub(1)));
+        self.frozen = true;
+    }
+
+    /// Let a frozen animation carry on from the frame it was held on
+    #[allow(dead_code)]
+    fn unfreeze(&mut self) {
+        self.frozen = false;
+    }
+
+    /// Step the current animation forward `n` frames without waiting on the clock
+    #[allow(dead_code)]
+    fn advance_frames(&mut self, n:u16) {
+        let frame_size = self.sprite_sheet.frame_size;
+        let frame_spacing = self.sprite_sheet.frame_spacing;
+        let sheet_dimensions = self.sprite_sheet.sheet_dimensions;
+        let current = self.sprite_sheet.current_animation;
+
+        self.sprite_sheet.animations[current].advance_frames(n, frame_size, frame_spacing, sheet_dimensions);
+    }
+
+    /// Index of the current animation's frame being shown
+    #[allow(dead_code)]
+    fn animation_frame(&self) -> u16 {
+        self.sprite_sheet.animations[self.sprite_sheet.current_animation].current_frame_index()
+    }
+
+    /// How far through its current frame the current animation is, from 0.0 to 1.0
+    #[allow(dead_code)]
+    fn animation_progress(&self) -> f32 {
+        self.sprite_sheet.animations[self.sprite_sheet.current_animation].progress()
+    }
+
+    /// Switch to the animation with the given name from its first frame
+    #[allow(dead_code)]
+    fn play(&mut self, name:&str) -> Result<(), AnimationNotFound> {
+        let index = self.sprite_sheet.animations
+            .iter()
+            .position(|animation| animation.name == name)
+            .ok_or_else(|| AnimationNotFound(name.to_string()))?;
+
+        self.sprite_sheet.current_animation = index;
+        self.restart_animation();
+        Ok(())
+    }
+
+    /// Switch to the next animation on the sheet from its first frame, wrapping around after the last
+    fn next_animation(&mut self) {
+        let count = self.sprite_sheet.animations.len().max(1);
+        self.sprite_sheet.current_animation = (self.sprite_sheet.current_animation + 1) % count;
+        self.restart_animation();
+    }
+
+    /// Switch to the previous animation on the sheet from its first frame, wrapping around before the first
+    fn prev_animation(&mut self) {
+        let count = self.sprite_sheet.animations.len().max(1);
+        self.sprite_sheet.current_animation = (self.sprite_sheet.current_animation + count - 1) % count;
+        self.restart_animation();
+    }
+
+    /// Restart the current animation from its first frame
+    fn restart_animation(&mut self) {
+        let current = self.sprite_sheet.current_animation;
+        self.sprite_sheet.animations[current].reset();
+    }
+
+    fn get_sheet_offset(&self) -> (u16, u16) {
+        let current = self.sprite_sheet.current_animation;
+        self.sprite_sheet.animations[current].current_position
+    }
+
+    fn get_sprite_sheet(&self) -> &DynamicImage {
+        &self.sprite_sheet.texture
+    }
+
+    #[allow(dead_code)]
+    fn flip_sprite(&mut self) -> bool {
+        self.facing_left = !self.facing_left;
+
+        true
+    }
+
+    fn facing_left(&self) -> bool {
+        self.facing_left
+    }
+
+    /// Keep the sprite's position within a box
+    #[allow(dead_code)]
+    fn clamp_position(&mut self, min:(f32, f32), max:(f32, f32)) {
+        self.position.0 = self.position.0.max(min.0).min(max.0);
+        self.position.1 = self.position.1.max(min.1).min(max.1);
+    }
+
+    /// Limit the sprite's speed, keeping the direction it is moving in
+    #[allow(dead_code)]
+    fn clamp_velocity(&mut self, max_speed:f32) {
+        let speed = self.velocity.0.hypot(self.velocity.1);
+        if speed > max_speed && speed > 0.0 {
+            let factor = max_speed.max(0.0) / speed;
+            self.velocity = (self.velocity.0 * factor, self.velocity.1 * factor);
+        }
+    }
+
+    /// Face the direction of horizontal movement, keeping the last facing when not moving
+    fn face_velocity(&mut self) {
+        if self.velocity.0 != 0.0 {
+            self.facing_left = self.velocity.0 < 0.0;
+        }
+    }
+}
+
+impl SpriteSheet {
+    fn new(texture:DynamicImage, animations:Vec<Animation>, frame_size:(u16, u16)) -> Self {
+        let height = texture.height();
+        let width = texture.width();
+        Self {
+            texture: Rc::new(texture),
+            frame_size,
+            animations,
+            current_animation: 0,
+            sheet_dimensions: (width as u16, height as u16),
+            texture_path: None,
+            texture_modified: None,
+            premultiplied: false,
+            frame_spacing: (0, 0),
+            alpha_mode: AlphaMode::Blend,
+        }
+    }
+
+    /// Create a sheet whose texture is shrunk by a whole factor until neither side is over `max_dimension`,
+    /// scaling the frame size and every animation's frame positions down with it. The factor has to divide
+    /// every frame's size and position so frames stay on whole pixels, if none that small does it's an error.
+    #[allow(dead_code)]
+    fn new_downscaled(texture:DynamicImage, mut animations:Vec<Animation>, frame_size:(u16, u16), max_dimension:u32) -> Result<Self, Box<dyn std::error::Error>> {
+        let largest = texture.width().max(texture.height());
+        if max_dimension == 0 || largest <= max_dimension {
+            return Ok(Self::new(texture, animations, frame_size));
+        }
+
+        // Every value a frame is placed or sized by, the factor has to divide all of them
+        let mut grid_values = vec![frame_size.0, frame_size.1];
+        for animation in &animations {
+            grid_values.extend([animation.starting_frame_position.0, animation.starting_frame_position.1]);
+            grid_values.extend([animation.current_position.0, animation.current_position.1]);
+            for frame in &animation.frames {
+                grid_values.extend([frame.position.0, frame.position.1, frame.size.0, frame.size.1]);
+            }
+        }
+
+        let smallest = largest.div_ceil(max_dimension);
+        let largest_factor = frame_size.0.min(frame_size.1) as u32;
+        let factor = (smallest..=largest_factor)
+            .find(|factor| grid_values.iter().all(|&value| (value as u32).is_multiple_of(*factor)))
+            .ok_or_else(|| format!(
+                "no factor from {smallest} to {largest_factor} divides the {}x{} frame grid evenly",
+                frame_size.0,
+                frame_size.1,
+            ))?;
+
+        let shrink = |value:u16| (value as u32 / factor) as u16;
+        let shrink_pair = |(x, y):(u16, u16)| (shrink(x), shrink(y));
+        for animation in &mut animations {
+            animation.starting_frame_position = shrink_pair(animation.starting_frame_position);
+            animation.current_position = shrink_pair(animation.current_position);
+            for frame in &mut animation.frames {
+                frame.position = shrink_pair(frame.position);
+                frame.size = shrink_pair(frame.size);
+            }
+        }
+
+        // Trim any leftover pixels past the last whole step of the factor so it scales exactly,
+        // no frame can reach into them as frames end on multiples of the factor
+        let (width, height) = (texture.width() / factor, texture.height() / factor);
+        let texture = texture
+            .crop_imm(0, 0, width * factor, height * factor)
+            .resize_exact(width, height, image::imageops::FilterType::Triangle);
+        info!("downscaled a {largest} pixel texture by a factor of {factor} to fit {max_dimension} pixels");
+
+        Ok(Self::new(texture, animations, shrink_pair(frame_size)))
+    }
+
+    /// Load the sheet's texture from a file, remembering the path so it can be reloaded
+    fn load(path:&Path, animations:Vec<Animation>, frame_size:(u16, u16)) -> image::ImageResult<Self> {
+        let mut sheet = Self::new(image::open(path)?, animations, frame_size);
+        sheet.texture_path = Some(path.to_path_buf());
+        sheet.texture_modified = modified_time(path);
+        Ok(sheet)
+    }
+
+    /// Create an animation that steps through every whole frame on the sheet, row by row
+    #[allow(dead_code)]
+    fn all_frames_animation(&self, frame_duration:u64) -> Animation {
+        let columns = self.grid_cells(self.sheet_dimensions.0, self.frame_size.0, self.frame_spacing.0);
+        let rows = self.grid_cells(self.sheet_dimensions.1, self.frame_size.1, self.frame_spacing.1);
+
+        Animation::new((0, 0), columns * rows, frame_duration)
+    }
+
+    /// Pixel position of the frame in a grid column and row, accounting for gutters
+    #[allow(dead_code)]
+    fn grid_position(&self, column:u16, row:u16) -> (u16, u16) {
+        (
+            column * (self.frame_size.0 + self.frame_spacing.0),
+            row * (self.frame_size.1 + self.frame_spacing.1),
+        )
+    }
+
+    /// How many whole frames of `frame` pixels separated by `spacing` fit in `sheet` pixels
+    #[allow(dead_code)]
+    fn grid_cells(&self, sheet:u16, frame:u16, spacing:u16) -> u16 {
+        if frame == 0 || sheet < frame {
+            return 0;
+        }
+        (sheet - frame) / (frame + spacing) + 1
+    }
+
+    /// Create a sheet from a single row of `num_frames` frames, each `frame_width` pixels wide and as tall
+    /// as the texture, with one animation playing along the strip
+    #[allow(dead_code)]
+    fn from_strip(texture:DynamicImage, frame_width:u16, num_frames:u16, frame_duration:u64) -> Self {
+        let frame_size = (frame_width, texture.height() as u16);
+        let frames = (0..num_frames).map(|i| Frame {
+            position: (i * frame_width, 0),
+            size: frame_size,
+            duration: None,
+        }).collect();
+
+        Self::new(texture, vec![Animation::from_frames(frames, frame_duration)], frame_size)
+    }
+
+    /// Load an animated GIF, laying its frames out in a strip with a single animation using the GIF's timing
+    #[allow(dead_code)]
+    fn from_gif(path:&Path) -> image::ImageResult<Self> {
+        let decoder = GifDecoder::new(fs::File::open(path)?)?;
+        let gif_frames = decoder.into_frames().collect_frames()?;
+
+        let (width, height) = gif_frames.first().map_or((0, 0), |frame| frame.buffer().dimensions());
+        let strip_width = width as usize * gif_frames.len();
+        if strip_width > u16::MAX as usize || height > u16::MAX as u32 {
+            return Err(ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError)));
+        }
+
+        // Copy every frame side by side into one texture
+        let mut texture = RgbaImage::new(strip_width as u32, height);
+        let mut frames = Vec::with_capacity(gif_frames.len());
+        for (i, gif_frame) in gif_frames.iter().enumerate() {
+            let x = i as u32 * width;
+            texture.copy_from(gif_frame.buffer(), x, 0)?;
+
+            // A zero delay falls back to the default duration like browsers do
+            let (numerator, denominator) = gif_frame.delay().numer_denom_ms();
+            let delay = (numerator / denominator.max(1)) as u64;
+            frames.push(Frame {
+                position: (x as u16, 0),
+                size: (width as u16, height as u16),
+                duration: Some(delay).filter(|&delay| delay > 0),
+            });
+        }
+
+        let animation = Animation::from_frames(frames, DEFAULT_FRAME_DURATION);
+        Ok(Self::new(DynamicImage::ImageRgba8(texture), vec![animation], (width as u16, height as u16)))
+    }
+
+    /// Load a sheet exported by Aseprite, creating one animation per frame tag.
+    /// Without any tags a single animation containing every frame is created.
+    #[allow(dead_code)]
+    fn from_aseprite(png_path:&Path, json_path:&Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let atlas: AsepriteAtlas = serde_json::from_str(&fs::read_to_string(json_path)?)?;
+
+        let aseprite_frames = match atlas.frames {
+            AsepriteFrames::Array(frames) => frames,
+            AsepriteFrames::Hash(frames) => frames
+                .into_iter()
+                .map(|(_, frame)| serde_json::from_value(frame))
+                .collect::<Result<_, _>>()?,
+        };
+
+        let frames: Vec<Frame> = aseprite_frames.iter().map(|frame| Frame {
+            position: (frame.frame.x, frame.frame.y),
+            size: (frame.frame.w, frame.frame.h),
+            duration: Some(frame.duration),
+        }).collect();
+
+        let Some(first_frame) = frames.first() else {
+            return Err(format!("{} has no frames", json_path.display()).into());
+        };
+        let frame_size = first_frame.size;
+
+        let mut animations = Vec::new();
+        for tag in &atlas.meta.frame_tags {
+            if tag.from > tag.to || tag.to >= frames.len() {
+                return Err(format!("tag {} refers to missing frames {}..={}", tag.name, tag.from, tag.to).into());
+            }
+
+            let mut tag_frames = frames[tag.from..=tag.to].to_vec();
+            match tag.direction.as_str() {
+                "reverse" => tag_frames.reverse(),
+                "pingpong" => {
+                    // Play back down without repeating the first and last frames
+                    let inner = tag_frames.len().saturating_sub(1);
+                    let returning: Vec<Frame> = tag_frames[1..inner.max(1)].iter().rev().copied().collect();
+                    tag_frames.extend(returning);
+                }
+                _ => {}
+            }
+
+            animations.push(Animation::from_frames(tag_frames, DEFAULT_FRAME_DURATION).named(&tag.name));
+        }
+
+        if animations.is_empty() {
+            animations.push(Animation::from_frames(frames, DEFAULT_FRAME_DURATION));
+        }
+
+        Ok(Self::load(png_path, animations, frame_size)?)
+    }
+
+    /// Alpha composite another sheet's texture on top of this one into a new sheet with this sheet's
+    /// animations, e.g. armor over a body. The sheets should share a layout, anything of `other` past
+    /// this sheet's edges is cut off. Both textures are treated as straight, not premultiplied, alpha.
+    #[allow(dead_code)]
+    fn overlay(&self, other:&SpriteSheet) -> SpriteSheet {
+        let mut texture = self.texture.to_rgba8();
+        image::imageops::overlay(&mut texture, &other.texture.to_rgba8(), 0, 0);
+
+        let mut combined = SpriteSheet::new(DynamicImage::ImageRgba8(texture), self.animations.clone(), self.frame_size);
+        combined.current_animation = self.current_animation;
+        combined.frame_spacing = self.frame_spacing;
+        combined.alpha_mode = self.alpha_mode;
+        combined
+    }
+
+    /// Lay out every frame of every animation side by side on a checkerboard, for previews
+    #[allow(dead_code)]
+    fn render_contact_sheet(&self) -> DynamicImage {
+        let frames: Vec<Frame> = self.animations.iter()
+            .flat_map(|animation| animation.frame_list(self.frame_size, self.frame_spacing, self.sheet_dimensions))
+            .collect();
+
+        // Every cell fits the largest frame, in a roughly square grid
+        let cell = frames.iter().fold((0, 0), |cell:(u32, u32), frame| {
+            (cell.0.max(frame.size.0 as u32), cell.1.max(frame.size.1 as u32))
+        });
+        let columns = (frames.len() as f32).sqrt().ceil().max(1.0) as u32;
+        let rows = (frames.len() as u32).div_ceil(columns);
+
+        let mut contact_sheet = RgbaImage::from_fn(cell.0 * columns, cell.1 * rows, |x, y| {
+            Rgba(CHECKER_COLORS[((x / CHECKER_SIZE + y / CHECKER_SIZE) % 2) as usize])
+        });
+
+        for (i, frame) in frames.iter().enumerate() {
+            let i = i as u32;
+            let region = image::imageops::crop_imm(
+                self.texture.as_ref(),
+                frame.position.0 as u32,
+                frame.position.1 as u32,
+                frame.size.0 as u32,
+                frame.size.1 as u32,
+            ).to_image();
+            image::imageops::overlay(
+                &mut contact_sheet,
+                &region,
+                ((i % columns) * cell.0) as i64,
+                ((i / columns) * cell.1) as i64,
+            );
+        }
+
+        DynamicImage::ImageRgba8(contact_sheet)
+    }
+
+    /// Reload the texture if its file changed on disk, returning true if it was reloaded
+    fn reload_if_changed(&mut self) -> bool {
+        let Some(path) = &self.texture_path else {
+            return false;
+        };
+
+        let modified = modified_time(path);
+        if modified == self.texture_modified {
+            return false;
+        }
+
+        // Keep the old texture if the new one can't be read, it may still be being written
+        match image::open(path) {
+            Ok(texture) => {
+                self.sheet_dimensions = (texture.width() as u16, texture.height() as u16);
+                self.texture = Rc::new(texture);
+                self.texture_modified = modified;
+                true
+            }
+            Err(err) => {
+                warn!("failed to reload {}: {err}", path.display());
+                false
+            }
+        }
+    }
+}
+
+/// Create a new animation instance
+impl Animation {
+    fn new(starting_frame_position:(u16, u16), num_frames:u16, frame_duration:u64) -> Self {
+        Self {
+            name: String::new(),
+            starting_frame_position,
+            num_frames,
+            frame_duration,
+            current_frame: 0,
+            current_position: starting_frame_position,
+            previous_frame_time: 0,
+            last_update_time: 0,
+            frames: Vec::new(),
+            loop_mode: LoopMode::Loop,
+            finished: false,
+            layout: AnimationLayout::RowMajor,
+        }
+    }
+
+    /// Create an animation from individually placed frames, which may differ in size
+    #[allow(dead_code)]
+    fn from_frames(frames:Vec<Frame>, frame_duration:u64) -> Self {
+        let starting_frame_position = frames.first().map_or((0, 0), |frame| frame.position);
+        let mut animation = Self::new(starting_frame_position, frames.len() as u16, frame_duration);
+        animation.frames = frames;
+        animation
+    }
+
+    /// Give the animation a name to play it by
+    fn named(mut self, name:&str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// How long the current frame is shown for in milliseconds
+    fn current_frame_duration(&self) -> u64 {
+        self.frames
+            .get(self.current_frame as usize)
+            .and_then(|frame| frame.duration)
+            .unwrap_or(self.frame_duration)
+    }
+
+    /// The size of the current frame, if this animation has explicit frames
+    fn current_frame_size(&self) -> Option<(u16, u16)> {
+        self.frames.get(self.current_frame as usize).map(|frame| frame.size)
+    }
+
+    /// Create an animation from grid coordinates on the sheet instead of pixel offsets
+    fn from_row(row:u16, start_col:u16, num_frames:u16, frame_size:(u16, u16), frame_duration:u64) -> Self {
+        Self::new((start_col * frame_size.0, row * frame_size.1), num_frames, frame_duration)
+    }
+
+    /// Rewind the animation back to its first frame
+    fn reset(&mut self) {
+        self.current_frame = 0;
+        self.current_position = self.starting_frame_position;
+        self.previous_frame_time = 0;
+        self.last_update_time = 0;
+        self.finished = false;
+    }
+
+    /// Index of the frame being shown
+    #[allow(dead_code)]
+    fn current_frame_index(&self) -> u16 {
+        self.current_frame
+    }
+
+    /// How far through the current frame's duration the animation was at its last update, from 0.0 to 1.0
+    #[allow(dead_code)]
+    fn progress(&self) -> f32 {
+        if self.finished {
+            return 1.0;
+        }
+
+        // Frames without a duration never advance
+        let duration = self.current_frame_duration();
+        if duration == 0 {
+            return 0.0;
+        }
+
+        ((self.last_update_time - self.previous_frame_time) as f32 / duration as f32).clamp(0.0, 1.0)
+    }
+
+    /// If the duration has elapsed by `current_time` (in milliseconds), increment the currently selected animation by 1 frame
+    fn increment_frame(&mut self, frame_size:(u16, u16), frame_spacing:(u16, u16), sheet_dimensions:(u16, u16), current_time:i128) {
+        self.last_update_time = current_time;
+        if self.current_frame_duration() == 0 || self.finished {
+            return;
+        }
+
+        // Only increment the frame if time has elapsed
+        if current_time - self.previous_frame_time < self.current_frame_duration() as i128 {
+            return;
+        }
+
+        self.advance_frames(1, frame_size, frame_spacing, sheet_dimensions);
+        self.previous_frame_time = current_time;
+    }
+
+    /// Step forward `n` frames straight away, regardless of how much time has passed
+    fn advance_frames(&mut self, n:u16, frame_size:(u16, u16), frame_spacing:(u16, u16), sheet_dimensions:(u16, u16)) {
+        for _ in 0..n {
+            if self.finished {
+                return;
+            }
+
+            if self.current_frame >= self.num_frames.saturating_sub(1) && self.loop_mode == LoopMode::Once {
+                // Hold the last frame once it has been shown for its full duration
+                self.finished = true;
+            } else if self.current_frame >= self.num_frames.saturating_sub(1) {
+                self.current_frame = 0;
+                self.current_position = self.starting_frame_position;
+            } else if !self.frames.is_empty() {
+                self.current_frame += 1;
+                self.current_position = self.frames[self.current_frame as usize].position;
+            } else {
+                self.current_position = self.next_grid_position(frame_size, frame_spacing, sheet_dimensions);
+                self.current_frame += 1;
+            }
+        }
+    }
+
+    /// Every frame this animation plays, in order, whether explicit or read from the grid
+    #[allow(dead_code)]
+    fn frame_list(&self, frame_size:(u16, u16), frame_spacing:(u16, u16), sheet_dimensions:(u16, u16)) -> Vec<Frame> {
+        if !self.frames.is_empty() {
+            return self.frames.clone();
+        }
+
+        // Walk a rewound copy along the grid
+        let mut walker = self.clone();
+        walker.reset();
+        let mut frames = Vec::with_capacity(self.num_frames as usize);
+        for _ in 0..self.num_frames {
+            frames.push(Frame {
+                position: walker.current_position,
+                size: frame_size,
+                duration: None,
+            });
+            walker.current_position = walker.next_grid_position(frame_size, frame_spacing, sheet_dimensions);
+        }
+        frames
+    }
+
+    /// Position of the frame after the current one on a grid sheet, skipping any gutters
+    /// between frames and wrapping at the sheet edge
+    fn next_grid_position(&self, frame_size:(u16, u16), frame_spacing:(u16, u16), sheet_dimensions:(u16, u16)) -> (u16, u16) {
+        let (x, y) = self.current_position;
+        let step = (frame_size.0 + frame_spacing.0, frame_size.1 + frame_spacing.1);
+        match self.layout {
+            AnimationLayout::RowMajor => {
+                if x + step.0 + frame_size.0 > sheet_dimensions.0 {
+                    (0, y + step.1)
+                } else {
+                    (x + step.0, y)
+                }
+            }
+            AnimationLayout::ColumnMajor => {
+                if y + step.1 + frame_size.1 > sheet_dimensions.1 {
+                    (x + step.0, 0)
+                } else {
+                    (x, y + step.1)
+                }
+            }
+        }
+    }
+}
+
+/// Create a new `World` instance that can draw sprites
+impl World {
+    fn new(sprites: Vec<Sprite>) -> Self {
+        let background_path = PathBuf::from("assets/images/bg.png");
+        let background_image = image::open(&background_path).unwrap();
+        Self::with_background(sprites, background_image, background_path)
+    }
+
+    /// Load the background and every sprite listed in a world manifest
+    #[allow(dead_code)]
+    fn from_manifest(path:&Path) -> Result<Self, LoadError> {
+        let manifest:WorldManifest = read_manifest(path)?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+
+        let mut sprites = Vec::with_capacity(manifest.sprites.len());
+        for sprite_path in &manifest.sprites {
+            let sprite_path = directory.join(sprite_path);
+            let sprite_manifest:SpriteManifest = read_manifest(&sprite_path)?;
+
+            let animations = sprite_manifest.animations.iter().map(|animation| Animation::from_row(
+                animation.row,
+                animation.start_col,
+                animation.frames,
+                sprite_manifest.frame_size,
+                animation.frame_duration,
+            ).named(&animation.name)).collect();
+
+            // Sheet paths are relative to the sprite manifest that names them
+            let texture_path = sprite_path.parent().unwrap_or(Path::new("")).join(&sprite_manifest.texture);
+            let sheet = SpriteSheet::load(&texture_path, animations, sprite_manifest.frame_size)
+                .map_err(|source| LoadError::Image { path: texture_path.clone(), source })?;
+
+            let mut sprite = Sprite::new(sheet);
+            sprite.position = sprite_manifest.position;
+            sprites.push(sprite);
+        }
+
+        let background_path = directory.join(&manifest.background);
+        let background_image = image::open(&background_path)
+            .map_err(|source| LoadError::Image { path: background_path.clone(), source })?;
+
+        Ok(Self::with_background(sprites, background_image, background_path))
+    }
+
+    /// Create a world with a background that has already been loaded from `background_path`
+    fn with_background(sprites: Vec<Sprite>, background_image:DynamicImage, background_path:PathBuf) -> Self {
+        Self {
+            right_held: false,
+            left_held: false,
+            background_image,
+            background_modified: modified_time(&background_path),
+            background_path,
+            sprites,
+            debug_bounds: false,
+            wrap_x: false,
+            input_smoothing: 0.0,
+            camera: (0.0, 0.0),
+            ui_sprites: Vec::new(),
+            physics_hz: 60,
+            physics_accumulator: 0.0,
+            physics_substeps: 1,
+            max_frame_skip: 5,
+            physics: PhysicsConfig::default(),
+            background_tint: [1.0, 1.0, 1.0],
+            target_frame_time: 1.0 / 60.0,
+            dropped_frames: 0,
+            culled_sprites: 0,
+            coordinate_system: CoordinateSystem::TopLeftYDown,
+            hot_reload: false,
+            last_reload_check: 0,
+            clip_stack: Vec::new(),
+            mouse_position: None,
+            cursor_sprite: None,
+            jump_requested: false,
+            frame_count: 0,
+            recording: None,
+            verify_replay: false,
+            time_scale: 1.0,
+            animation_clock: 0.0,
+            clock_mode: ClockMode::WallClock,
+            last_clock_update: None,
+            editor_mode: false,
+            editor_camera: EditorCamera::default(),
+            camera_zoom: 1.0,
+            pixel_format: PixelFormat::Rgba8,
+            physics_paused: false,
+            animations_paused: false,
+            fade: None,
+            events: Vec::new(),
+            contacts: Vec::new(),
+            wall_contact: None,
+            collision_callbacks: Vec::new(),
+            max_sprites: None,
+            force_fields: Vec::new(),
+            layers: HashMap::new(),
+            background_sampling: BackgroundSampling::Nearest,
+            render_scale: 1,
+            tweens: Vec::new(),
+            sort_policy: SortPolicy::SpawnOrder,
+            static_background: false,
+            background_cache: None,
+            previous_frame: None,
+            letterbox_color: [0, 0, 0, 255],
+            input_history: InputHistory::default(),
+            background: Background::Image,
+            collision_map: None,
+            player_bounds: None,
+            motion_blur: 0.0,
+            motion_blur_frame: None,
+            screen_shake: None,
+            hit_stop: 0.0,
+            rng: StdRng::seed_from_u64(DEFAULT_RNG_SEED),
+            viewports: Vec::new(),
+        }
+    }
+
+    /// Size of the frame buffer the world draws into, the world size times `render_scale`
+    fn render_size(&self) -> (u32, u32) {
+        let scale = self.render_scale.max(1);
+        (WORLD_WIDTH * scale, WORLD_HEIGHT * scale)
+    }
+
+    /// Convert a world position to the output pixel it's drawn at
+    fn world_to_render(&self, position:(f32, f32), height:u16, camera:(i32, i32)) -> (i32, i32) {
+        let scale = self.render_scale.max(1) as i32;
+        let (x, y) = self.world_to_screen(position, height, camera);
+        (x.saturating_mul(scale), y.saturating_mul(scale))
+    }
+
+    /// Start animating a sprite property, it takes the tween's starting value straight away
+    #[allow(dead_code)]
+    fn add_tween(&mut self, tween:Tween) {
+        self.apply_tween(&tween);
+        self.tweens.push(tween);
+    }
+
+    /// Set the property a tween animates to the tween's current value
+    fn apply_tween(&mut self, tween:&Tween) {
+        let Some(sprite) = self.sprites.get_mut(tween.sprite) else {
+            return;
+        };
+
+        let value = tween.value();
+        match tween.target_field {
+            TweenField::PositionX => sprite.position.0 = value,
+            TweenField::PositionY => sprite.position.1 = value,
+            TweenField::ScaleX => sprite.scale.0 = value,
+            TweenField::ScaleY => sprite.scale.1 = value,
+            TweenField::Opacity => sprite.opacity = value.clamp(0.0, 1.0),
+            TweenField::Rotation => sprite.rotation = value,
+        }
+    }
+
+    /// Advance every tween by `dt` seconds, dropping those that have finished
+    fn update_tweens(&mut self, dt:f64) {
+        let mut tweens = std::mem::take(&mut self.tweens);
+        for tween in tweens.iter_mut() {
+            tween.elapsed = (tween.elapsed + dt).min(tween.duration);
+            self.apply_tween(tween);
+        }
+
+        tweens.retain(|tween| !tween.finished() && tween.sprite < self.sprites.len());
+        self.tweens = tweens;
+    }
+
+    /// Indices of the world sprites in the order they are updated, lowest priority first then in spawn order
+    fn update_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.sprites.len()).collect();
+        order.sort_by_key(|&i| self.sprites[i].update_priority);
+        order
+    }
+
+    /// Indices of the world sprites in the order they are drawn, back to front
+    fn draw_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.sprites.len()).collect();
+        if self.sort_policy == SortPolicy::ReverseSpawnOrder {
+            order.reverse();
+        }
+
+        // A stable sort keeps the tie break order chosen above, pinned sprites go last
+        order.sort_by_key(|&i| {
+            let sprite = &self.sprites[i];
+            match sprite.draw_priority {
+                Some(priority) => (1, priority as i64),
+                None => (0, sprite.z_index as i64),
+            }
+        });
+        order
+    }
+
+    /// Settings of a sprite layer
+    fn layer(&self, layer:u8) -> Layer {
+        self.layers.get(&layer).copied().unwrap_or_default()
+    }
+
+    /// Show or hide every sprite on a layer
+    #[allow(dead_code)]
+    fn set_layer_visible(&mut self, layer:u8, visible:bool) {
+        self.layers.entry(layer).or_default().visible = visible;
+    }
+
+    /// Fade every sprite on a layer, from 0.0 invisible to 1.0 opaque
+    #[allow(dead_code)]
+    fn set_layer_opacity(&mut self, layer:u8, opacity:f32) {
+        self.layers.entry(layer).or_default().opacity = opacity.clamp(0.0, 1.0);
+    }
+
+    /// Take every event raised since the last call
+    fn poll_events(&mut self) -> Vec<EngineEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Raise events for sprites that started overlapping this step
+    fn detect_collisions(&mut self) {
+        let bounds: Vec<Rect> = self.sprites.iter().map(Sprite::bounds).collect();
+
+        let mut contacts = Vec::new();
+        for (a, bounds_a) in bounds.iter().enumerate() {
+            for (b, bounds_b) in bounds.iter().enumerate().skip(a + 1) {
+                let overlap = bounds_a.intersect(bounds_b);
+                if overlap.width > 0 && overlap.height > 0 {
+                    contacts.push((a, b));
+                }
+            }
+        }
+
+        let mut callbacks = std::mem::take(&mut self.collision_callbacks);
+        for &(a, b) in &contacts {
+            if self.contacts.contains(&(a, b)) {
+                continue;
+            }
+
+            self.events.push(EngineEvent::Collision { a, b });
+            for (first, second, callback) in &mut callbacks {
+                let (sprite_a, sprite_b) = (&self.sprites[a], &self.sprites[b]);
+                if first.matches(a, sprite_a) && second.matches(b, sprite_b) {
+                    callback(a, b);
+                } else if first.matches(b, sprite_b) && second.matches(a, sprite_a) {
+                    callback(b, a);
+                }
+            }
+        }
+        self.collision_callbacks = callbacks;
+        self.contacts = contacts;
+    }
+
+    /// Run a callback whenever two sprites start overlapping, not again until they separate
+    #[allow(dead_code)]
+    fn on_collision(&mut self, a:usize, b:usize, callback:CollisionCallback) {
+        self.collision_callbacks.push((CollisionTarget::Sprite(a), CollisionTarget::Sprite(b), callback));
+    }
+
+    /// Run a callback whenever a sprite on one layer starts overlapping a sprite on another
+    #[allow(dead_code)]
+    fn on_layer_collision(&mut self, a:u8, b:u8, callback:CollisionCallback) {
+        self.collision_callbacks.push((CollisionTarget::Layer(a), CollisionTarget::Layer(b), callback));
+    }
+
+    /// Fade the whole screen to or from a color over `duration_ms` of game time.
+    /// A finished fade out keeps covering the screen until another fade is started.
+    #[allow(dead_code)]
+    fn start_fade(&mut self, color:[u8; 3], duration_ms:u64, direction:FadeDirection) {
+        let start_time = self.animation_time();
+        self.fade = Some(Fade {
+            color,
+            duration: duration_ms,
+            direction,
+            start_time,
+        });
+    }
+
+    /// Blend the fade overlay over the whole frame
+    fn draw_fade(&mut self, frame: &mut [u8]) {
+        let Some(fade) = self.fade else {
+            return;
+        };
+
+        let alpha = fade.alpha(self.animation_clock);
+
+        // A completed fade in has nothing left to draw
+        if fade.direction == FadeDirection::In && alpha <= 0.0 {
+            self.fade = None;
+            return;
+        }
+
+        for pixel in frame.chunks_exact_mut(4) {
+            for (channel, color) in pixel.iter_mut().zip(fade.color) {
+                *channel = (*channel as f32 * (1.0 - alpha) + color as f32 * alpha) as u8;
+            }
+        }
+    }
+
+    /// Pause or resume everything, resuming if only one of physics or animations was paused
+    fn toggle_pause(&mut self) {
+        let paused = !(self.physics_paused && self.animations_paused);
+        self.physics_paused = paused;
+        self.animations_paused = paused;
+    }
+
+    /// Pause or resume only the physics simulation
+    #[allow(dead_code)]
+    fn toggle_physics_pause(&mut self) {
+        self.physics_paused = !self.physics_paused;
+    }
+
+    /// Pause or resume only sprite animations
+    #[allow(dead_code)]
+    fn toggle_animations_pause(&mut self) {
+        self.animations_paused = !self.animations_paused;
+    }
+
+    /// Switch between controlling the player and freely moving the camera
+    fn set_editor_mode(&mut self, enabled:bool) {
+        self.editor_mode = enabled;
+
+        // Don't leave keys stuck down from the mode that was left
+        self.left_held = false;
+        self.right_held = false;
+        self.editor_camera = EditorCamera::default();
+    }
+
+    /// Zoom the editor camera in or out by a number of mouse wheel steps
+    fn zoom_editor(&mut self, steps:f32) {
+        if !self.editor_mode {
+            return;
+        }
+
+        self.camera_zoom = (self.camera_zoom * 1.25_f32.powf(steps)).clamp(1.0, EDITOR_MAX_ZOOM);
+    }
+
+    /// Pan the camera with the held editor keys
+    fn update_editor_camera(&mut self) {
+        if !self.editor_mode {
+            return;
+        }
+
+        let (x, y) = self.editor_camera.direction();
+        let speed = EDITOR_PAN_SPEED / self.camera_zoom;
+        self.camera.0 += x * speed;
+        self.camera.1 += y * speed * self.down_direction();
+    }
+
+    /// Enlarge the top-left of the frame by the camera zoom so it fills the whole frame
+    fn apply_zoom(&self, frame: &mut [u8]) {
+        if self.camera_zoom <= 1.0 {
+            return;
+        }
+
+        let width = self.render_size().0 as usize;
+        let unzoomed = frame.to_vec();
+        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+            let x = ((i % width) as f32 / self.camera_zoom) as usize;
+            let y = ((i / width) as f32 / self.camera_zoom) as usize;
+
+            let source = (y * width + x) * 4;
+            pixel.copy_from_slice(&unzoomed[source..source + 4]);
+        }
+    }
+
+    /// Start appending the input and sprite positions of every physics step to a file
+    #[allow(dead_code)]
+    fn start_recording(&mut self, path:&Path) -> Result<(), Box<dyn std::error::Error>> {
+        let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        self.recording = Some(BufWriter::new(file));
+        Ok(())
+    }
+
+    /// Stop recording and flush anything not yet written
+    #[allow(dead_code)]
+    fn stop_recording(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(mut recording) = self.recording.take() {
+            recording.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Feed the inputs of a recording back through the physics, one step per record.
+    /// Returns the records produced by the replay so they can be compared to the originals.
+    /// With `verify_replay` set, fails on the first step whose state differs from the recording.
+    #[allow(dead_code)]
+    fn replay(&mut self, path:&Path) -> Result<Vec<FrameRecord>, Box<dyn std::error::Error>> {
+        let step_time = 1.0 / self.physics_hz.max(1) as f64;
+        let mut replayed = Vec::new();
+
+        for line in BufReader::new(fs::File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let record: FrameRecord = ron::from_str(&line)?;
+            self.apply_input(record.input);
+            self.physics_step(step_time);
+            let replayed_record = self.frame_record(record.input);
+
+            if self.verify_replay && record.state_hash.is_some() && record.state_hash != replayed_record.state_hash {
+                return Err(format!("replay diverged from the recording at frame {}", record.frame).into());
+            }
+
+            replayed.push(replayed_record);
+        }
+
+        Ok(replayed)
+    }
+
+    /// The current input, as it will be seen by the next physics step
+    fn input_state(&self) -> InputState {
+        InputState {
+            left: self.left_held,
+            right: self.right_held,
+            jump: self.jump_requested,
+        }
+    }
+
+    /// Replace the current input
+    #[allow(dead_code)]
+    fn apply_input(&mut self, input:InputState) {
+        self.left_held = input.left;
+        self.right_held = input.right;
+        self.jump_requested = input.jump;
+    }
+
+    /// Remember that an action was pressed during the current physics step
+    fn record_press(&mut self, action:Action) {
+        self.input_history.push(action, self.frame_count);
+    }
+
+    /// Whether `sequence` was just completed within the last `window_frames` physics steps
+    #[allow(dead_code)]
+    fn matches_sequence(&self, sequence:&[Action], window_frames:u64) -> bool {
+        self.input_history.matches_sequence(sequence, window_frames, self.frame_count)
+    }
+
+    /// Make the player jump on the next physics step
+    fn jump(&mut self) {
+        if !self.editor_mode {
+            self.jump_requested = true;
+        }
+    }
+
+    /// A record of the current frame's sprite positions with the input that produced them
+    fn frame_record(&self, input:InputState) -> FrameRecord {
+        FrameRecord {
+            frame: self.frame_count,
+            input,
+            positions: self.sprites_iter().map(|sprite| sprite.position).collect(),
+            state_hash: Some(self.state_hash()),
+        }
+    }
+
+    /// FNV-1a hash of the exact bits of every sprite's position and velocity, stable between runs and builds
+    fn state_hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for sprite in self.sprites_iter() {
+            let values = [sprite.position.0, sprite.position.1, sprite.velocity.0, sprite.velocity.1];
+            for byte in values.iter().flat_map(|value| value.to_bits().to_le_bytes()) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        hash
+    }
+
+    /// Append a record to the recording file, stopping the recording if it can't be written
+    fn write_record(&mut self, record:&FrameRecord) {
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+
+        let result = ron::to_string(record)
+            .map_err(Box::<dyn std::error::Error>::from)
+            .and_then(|line| Ok(writeln!(recording, "{line}")?));
+
+        if let Err(err) = result {
+            warn!("stopping recording, failed to write frame {}: {err}", record.frame);
+            self.recording = None;
+        }
+    }
+
+    /// Update the mouse position in screen pixels, `None` when it leaves the window
+    fn set_mouse_position(&mut self, position:Option<(f32, f32)>) {
+        self.mouse_position = position;
+    }
+
+    /// The mouse position converted to world coordinates
+    #[allow(dead_code)]
+    fn mouse_world_position(&self) -> Option<(f32, f32)> {
+        self.mouse_position.map(|(x, y)| self.screen_to_world((x / self.camera_zoom, y / self.camera_zoom), 0))
+    }
+
+    /// Use a sprite as the mouse cursor, its top-left corner is placed at the mouse
+    #[allow(dead_code)]
+    fn set_cursor_sprite(&mut self, sprite:Sprite) {
+        self.cursor_sprite = Some(sprite);
+    }
+
+    /// Move the cursor sprite to the current mouse position
+    fn update_cursor(&mut self) {
+        let (Some(mouse), Some(cursor)) = (self.mouse_position, &self.cursor_sprite) else {
+            return;
+        };
+
+        let position = self.screen_to_world(mouse, cursor.scaled_size().1);
+        if let Some(cursor) = &mut self.cursor_sprite {
+            cursor.position = position;
+        }
+    }
+
+    /// Limit drawing to a rectangle, within any clip that is already active
+    fn push_clip(&mut self, rect:Rect) {
+        let clip = match self.clip_stack.last() {
+            Some(current) => current.intersect(&rect),
+            None => rect,
+        };
+        self.clip_stack.push(clip);
+    }
+
+    /// Remove the most recently pushed clip rectangle
+    fn pop_clip(&mut self) -> Option<Rect> {
+        self.clip_stack.pop()
+    }
+
+    /// Whether a pixel may be drawn to under the current clip
+    fn in_clip(&self, x:i32, y:i32) -> bool {
+        self.clip_stack.last().is_none_or(|clip| clip.contains(x, y))
+    }
+
+    /// Enable or disable reloading textures when their files change on disk
+    #[allow(dead_code)]
+    fn enable_hot_reload(&mut self, enabled:bool) {
+        self.hot_reload = enabled;
+    }
+
+    /// Every texture the world holds with its size, listing textures shared between sprites once
+    #[allow(dead_code)]
+    fn asset_report(&self) -> Vec<AssetInfo> {
+        let mut report = vec![AssetInfo::new(Some(self.background_path.clone()), &self.background_image)];
+
+        let mut sheets: Vec<&SpriteSheet> = self.sprites
+            .iter()
+            .chain(self.ui_sprites.iter())
+            .chain(self.cursor_sprite.iter())
+            .map(|sprite| &sprite.sprite_sheet)
+            .collect();
+        if let Background::AnimatedTile(sheet) = &self.background {
+            sheets.push(sheet);
+        }
+
+        let mut seen: Vec<&Rc<DynamicImage>> = Vec::new();
+        for sheet in sheets {
+            if seen.iter().any(|texture| Rc::ptr_eq(texture, &sheet.texture)) {
+                continue;
+            }
+
+            seen.push(&sheet.texture);
+            report.push(AssetInfo::new(sheet.texture_path.clone(), &sheet.texture));
+        }
+
+        report
+    }
+
+    /// Reload the background and any sprite sheets whose files changed, returning how many were reloaded
+    fn reload_changed_assets(&mut self) -> usize {
+        let mut reloaded = 0;
+
+        let modified = modified_time(&self.background_path);
+        if modified != self.background_modified {
+            match image::open(&self.background_path) {
+                Ok(image) => {
+                    self.background_image = image;
+                    self.invalidate_background();
+                    self.background_modified = modified;
+                    reloaded += 1;
+                }
+                Err(err) => warn!("failed to reload {}: {err}", self.background_path.display()),
+            }
+        }
+
+        for sprite in self.sprites.iter_mut().chain(self.ui_sprites.iter_mut()) {
+            if sprite.sprite_sheet.reload_if_changed() {
+                reloaded += 1;
+            }
+        }
+
+        reloaded
+    }
+
+    /// Advance the simulation by `dt` seconds, running as many fixed physics steps as fit, up to `max_frame_skip`.
+    /// Returns the number of physics steps that were run.
+    fn step(&mut self, mut dt:f64) -> u32 {
+        self.update_screen_shake(dt);
+
+        // Hit stop swallows real time before any of it reaches the simulation
+        if self.hit_stop > 0.0 {
+            let frozen = self.hit_stop.min(dt);
+            self.hit_stop -= frozen;
+            dt -= frozen;
+        }
+
+        if self.physics_hz == 0 || self.physics_paused {
+            return 0;
+        }
+
+        let step_time = 1.0 / self.physics_hz as f64;
+        self.physics_accumulator += dt * self.time_scale.max(0.0) as f64;
+
+        let max_steps = self.max_frame_skip.max(1);
+        let mut steps = 0;
+        while self.physics_accumulator >= step_time && steps < max_steps {
+            self.physics_step(step_time);
+            self.physics_accumulator -= step_time;
+            steps += 1;
+        }
+
+        // Drop whole steps that couldn't be caught up on so a slow frame can't snowball
+        if steps == max_steps {
+            self.physics_accumulator = self.physics_accumulator.rem_euclid(step_time);
+        }
+
+        steps
+    }
+
+    /// Route a feedback event to the subsystem that produces it
+    #[allow(dead_code)]
+    fn emit_feedback(&mut self, event:FeedbackEvent) {
+        match event {
+            // Without a gamepad to vibrate, rumble is felt through the screen instead
+            FeedbackEvent::Rumble { intensity, duration } | FeedbackEvent::ScreenShake { intensity, duration } => {
+                // A weaker shake doesn't cut short a stronger one that's still playing
+                let stronger = self.screen_shake.is_some_and(|shake| shake.offset_strength() > intensity);
+                if !stronger {
+                    self.screen_shake = Some(ScreenShake { intensity, duration, elapsed: 0.0 });
+                }
+            }
+            FeedbackEvent::HitStop { duration } => self.hit_stop = self.hit_stop.max(duration),
+        }
+    }
+
+    /// Advance the screen shake by `dt` seconds, ending it once its duration is up
+    fn update_screen_shake(&mut self, dt:f64) {
+        if let Some(shake) = &mut self.screen_shake {
+            shake.elapsed += dt;
+            if shake.elapsed >= shake.duration {
+                self.screen_shake = None;
+            }
+        }
+    }
+
+    /// Run a single fixed physics step of `step_time` seconds
+    fn physics_step(&mut self, step_time:f64) {
+        let input = self.input_state();
+
+        self.update_editor_camera();
+        self.update_idle(step_time);
+        self.update_movement();
+        self.update_behaviors(step_time);
+        self.update_tweens(step_time);
+        self.update_physics();
+        self.update_rotation(step_time);
+        self.update_facing();
+        self.update_attachments();
+        self.despawn_sprites();
+        self.detect_collisions();
+        self.frame_count += 1;
+
+        if self.recording.is_some() {
+            let record = self.frame_record(input);
+            self.write_record(&record);
+        }
+    }
+
+    /// Track how long the last frame took, returning true if it counted as dropped
+    fn record_frame_time(&mut self, frame_time:f64) -> bool {
+        if frame_time > self.target_frame_time * DROPPED_FRAME_THRESHOLD {
+            self.dropped_frames += 1;
+            return true;
+        }
+
+        false
+    }
+
+    /// How many frames have been dropped so far
+    #[allow(dead_code)]
+    fn dropped_frames(&self) -> u64 {
+        self.dropped_frames
+    }
+
+    /// How many sprites were skipped in the last draw for being off screen
+    #[allow(dead_code)]
+    fn culled_sprites(&self) -> u32 {
+        self.culled_sprites
+    }
+
+    /// Add a sprite to the world, returning its index, unless the world is already at `max_sprites`
+    #[allow(dead_code)]
+    fn spawn(&mut self, sprite:Sprite) -> Result<usize, SpawnError> {
+        if let Some(max_sprites) = self.max_sprites {
+            if self.sprites.len() >= max_sprites {
+                return Err(SpawnError::AtCapacity(max_sprites));
+            }
+        }
+
+        self.sprites.push(sprite);
+        Ok(self.sprites.len() - 1)
+    }
+
+    /// Add a sprite made from a prototype at a world position, returning its index
+    #[allow(dead_code)]
+    fn spawn_from(&mut self, prototype:&SpritePrototype, position:(f32, f32)) -> Result<usize, SpawnError> {
+        self.spawn(prototype.instantiate(position))
+    }
+
+    /// Add a sprite made from one of several prototypes, picked at random in proportion to its weight
+    #[allow(dead_code)]
+    fn spawn_weighted(&mut self, choices:&[(SpritePrototype, f32)], position:(f32, f32)) -> Result<usize, SpawnError> {
+        let weights = WeightedIndex::new(choices.iter().map(|(_, weight)| *weight))
+            .map_err(|_| SpawnError::InvalidWeights)?;
+        let choice = self.rng.sample(weights);
+
+        self.spawn_from(&choices[choice].0, position)
+    }
+
+    /// Restart the world's random number generator from a seed
+    #[allow(dead_code)]
+    fn seed_rng(&mut self, seed:u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Add a sprite that plays an animation once and then removes itself, returning its index
+    #[allow(dead_code)]
+    fn spawn_effect(&mut self, mut sprite:Sprite, animation_index:usize) -> Result<usize, SpawnError> {
+        sprite.sprite_sheet.current_animation = animation_index;
+        sprite.sprite_sheet.animations[animation_index].loop_mode = LoopMode::Once;
+        sprite.restart_animation();
+        sprite.despawn_on_finish = true;
+
+        self.spawn(sprite)
+    }
+
+    /// Iterate over all world sprites
+    fn sprites_iter(&self) -> impl Iterator<Item = &Sprite> {
+        self.sprites.iter()
+    }
+
+    /// Iterate over all world sprites with mutable access
+    fn sprites_iter_mut(&mut self) -> impl Iterator<Item = &mut Sprite> {
+        self.sprites.iter_mut()
+    }
+
+    /// Capture the current positions, velocities, animations and camera
+    fn snapshot(&self) -> WorldState {
+        WorldState {
+            camera: self.camera,
+            sprites: self.sprites.iter().map(|sprite| {
+                let current = sprite.sprite_sheet.current_animation;
+                let animation = &sprite.sprite_sheet.animations[current];
+                SpriteState {
+                    position: sprite.position,
+                    velocity: sprite.velocity,
+                    facing_left: sprite.facing_left,
+                    current_animation: current,
+                    current_frame: animation.current_frame,
+                    current_position: animation.current_position,
+                }
+            }).collect(),
+        }
+    }
+
+    /// Apply a snapshot to this world, which must contain the same sprites it was taken from
+    #[allow(dead_code)]
+    fn restore(&mut self, state:&WorldState) -> Result<(), Box<dyn std::error::Error>> {
+        if state.sprites.len() != self.sprites.len() {
+            return Err(format!(
+                "saved state has {} sprites but the world has {}",
+                state.sprites.len(),
+                self.sprites.len(),
+            ).into());
+        }
+
+        for (sprite, saved) in self.sprites.iter_mut().zip(&state.sprites) {
+            if saved.current_animation >= sprite.sprite_sheet.animations.len() {
+                return Err(format!("saved animation index {} does not exist", saved.current_animation).into());
+            }
+
+            sprite.position = saved.position;
+            sprite.velocity = saved.velocity;
+            sprite.facing_left = saved.facing_left;
+            sprite.sprite_sheet.current_animation = saved.current_animation;
+
+            let animation = &mut sprite.sprite_sheet.animations[saved.current_animation];
+            animation.current_frame = saved.current_frame;
+            animation.current_position = saved.current_position;
+        }
+
+        self.camera = state.camera;
+        Ok(())
+    }
+
+    /// Save the world state to a RON file
+    #[allow(dead_code)]
+    fn save_state(&self, path:&Path) -> Result<(), Box<dyn std::error::Error>> {
+        let serialized = ron::ser::to_string_pretty(&self.snapshot(), ron::ser::PrettyConfig::default())?;
+        fs::write(path, serialized)?;
+        Ok(())
+    }
+
+    /// Load a world state previously written by `save_state`
+    #[allow(dead_code)]
+    fn load_state(&mut self, path:&Path) -> Result<(), Box<dyn std::error::Error>> {
+        let state: WorldState = ron::from_str(&fs::read_to_string(path)?)?;
+        self.restore(&state)
+    }
+
+    /// Flip the background horizontally, useful for mirrored level variants
+    #[allow(dead_code)]
+    fn mirror_background(&mut self) {
+        self.background_image = self.background_image.fliph();
+        self.invalidate_background();
+    }
+
+    /// Throw away the cached static background, needed after changing `background_image` directly
+    fn invalidate_background(&mut self) {
+        self.background_cache = None;
+    }
+
+    /// Move the camera so the given world position is at the top-left of the screen
+    #[allow(dead_code)]
+    fn set_camera(&mut self, x:f32, y:f32) {
+        self.camera = (x, y);
+    }
+
+    /// Top-left screen position of a UI sprite, taking its screen anchor into account
+    fn ui_screen_position(&self, sprite:&Sprite) -> (i32, i32) {
+        let Some(anchor) = sprite.screen_anchor else {
+            return self.world_to_screen(sprite.position, sprite.scaled_size().1, (0, 0));
+        };
+
+        let (width, height) = sprite.scaled_size();
+        let area = (WORLD_WIDTH as f32, WORLD_HEIGHT as f32);
+        let (x, y) = anchor.place((width as f32, height as f32), sprite.position, area);
+        (x as i32, y as i32)
+    }
+
+    /// The camera offset that draws a UI sprite at its screen position
+    fn ui_camera(&self, sprite:&Sprite) -> (i32, i32) {
+        let (x, y) = self.ui_screen_position(sprite);
+        let (unmoved_x, unmoved_y) = self.world_to_screen(sprite.position, sprite.scaled_size().1, (0, 0));
+        let down = self.down_direction() as i32;
+        (unmoved_x - x, (unmoved_y - y) * down)
+    }
+
+    /// Add a sprite to the UI layer, returning its index
+    #[allow(dead_code)]
+    fn add_ui_sprite(&mut self, sprite:Sprite) -> usize {
+        self.ui_sprites.push(sprite);
+        self.ui_sprites.len() - 1
+    }
+
+    /// Enable or disable drawing an outline around every sprite's bounding box
+    fn set_debug_bounds(&mut self, enabled:bool) {
+        self.debug_bounds = enabled;
+    }
+
+    /// Set the animation to use in the
+    fn set_sprite_animation(&mut self, sprite:usize, animation_index:usize) {
+        self.sprites[sprite].sprite_sheet.current_animation = animation_index;
+    }
+
+    /// Update held/released keys
+    fn key_held(&mut self, key_id:VirtualKeyCode) {
+        // The editor camera takes over movement keys
+        if self.editor_mode && self.editor_camera.set_key(key_id, true) {
+            return;
+        }
+
+        if key_id == VirtualKeyCode::Right {
+            self.right_held = true;
+        }
+        if key_id == VirtualKeyCode::Left {
+            self.left_held = true;
+        }
+    }
+
+    fn key_released(&mut self, key_id:VirtualKeyCode) {
+        self.editor_camera.set_key(key_id, false);
+
+        if key_id == VirtualKeyCode::Right {
+            self.right_held = false;
+        }
+        if key_id == VirtualKeyCode::Left {
+            self.left_held = false;
+        }
+    }
+
+    #[allow(dead_code)]
+    fn set_velocity_y(&mut self, velocity:f32, sprite_index:usize) {
+        self.sprites[sprite_index].velocity.1 = velocity;
+    }
+
+    /// The held horizontal direction, -1.0 for left, 1.0 for right and 0.0 for neither or both
+    fn horizontal_axis(&self) -> f32 {
+        self.right_held as i8 as f32 - self.left_held as i8 as f32
+    }
+
+    fn update_movement(&mut self) {
+        if self.jump_requested {
+            let axis = self.fall_axis();
+            *component_mut(&mut self.sprites[0].velocity, axis) = -JUMP_VELOCITY * self.fall_direction();
+            self.jump_requested = false;
+        }
+
+        let direction = self.horizontal_axis();
+        if self.input_smoothing > 0.0 {
+            // Ease the velocity towards the target speed over several frames
+            let target = direction * MAX_RUN_SPEED;
+            let rate = 1.0 - self.input_smoothing.clamp(0.0, 0.99);
+            self.sprites[0].velocity.0 += (target - self.sprites[0].velocity.0) * rate;
+        } else if self.sprites[0].velocity.0.abs() < MAX_RUN_SPEED {
+            self.sprites[0].velocity.0 += 0.3 * direction;
+        }
+
+        // Smooth out floating point errors
+        self.sprites[0].velocity.0 = (self.sprites[0].velocity.0 * 1000.0).round() / 1000.0;
+
+        if !self.sprites[0].auto_face {
+            self.sprites[0].facing_left = self.sprites[0].velocity.0 < 0.0;
+        }
+    }
+
+    /// Switch the player to its idle animation once it's gone `idle_timeout_ms` without movement input,
+    /// and back to what it was playing as soon as movement resumes
+    fn update_idle(&mut self, dt:f64) {
+        let moving = self.jump_requested || self.horizontal_axis() != 0.0;
+        let player = &mut self.sprites[0];
+        let Some(timeout) = player.idle_timeout_ms else {
+            return;
+        };
+
+        if moving {
+            player.idle_elapsed_ms = 0.0;
+            if let Some(previous) = player.animation_before_idle.take() {
+                player.sprite_sheet.current_animation = previous;
+                player.restart_animation();
+            }
+            return;
+        }
+
+        player.idle_elapsed_ms += dt * 1000.0;
+        let idle = player.idle_animation;
+        if player.idle_elapsed_ms >= timeout as f64
+            && player.animation_before_idle.is_none()
+            && idle < player.sprite_sheet.animations.len()
+        {
+            player.animation_before_idle = Some(player.sprite_sheet.current_animation);
+            player.sprite_sheet.current_animation = idle;
+            player.restart_animation();
+        }
+    }
+
+    /// Remove finished one-shot effects and sprites that left the world. The player is never removed
+    /// so it stays at index 0.
+    fn despawn_sprites(&mut self) {
+        let removed: Vec<bool> = self.sprites
+            .iter()
+            .enumerate()
+            .map(|(i, sprite)| i != 0 && sprite.should_despawn(self.wrap_x))
+            .collect();
+
+        if removed.contains(&true) {
+            self.remove_sprites(&removed);
+        }
+    }
+
+    /// Remove every sprite marked in `removed`, moving every stored sprite index to where its sprite
+    /// ends up and dropping anything that referred to a removed sprite
+    fn remove_sprites(&mut self, removed:&[bool]) {
+        let mut kept = 0;
+        let remap: Vec<Option<usize>> = removed.iter().map(|&gone| {
+            if gone {
+                return None;
+            }
+
+            kept += 1;
+            Some(kept - 1)
+        }).collect();
+        let new_index = |index:usize| remap.get(index).copied().flatten();
+
+        let mut index = 0;
+        self.sprites.retain(|_| {
+            index += 1;
+            !removed.get(index - 1).copied().unwrap_or(false)
+        });
+
+        // A sprite whose parent is gone stays where it is
+        for sprite in &mut self.sprites {
+            sprite.parent = sprite.parent.and_then(new_index);
+        }
+        for viewport in &mut self.viewports {
+            viewport.follow_sprite = viewport.follow_sprite.and_then(new_index);
+        }
+
+        self.tweens.retain_mut(|tween| match new_index(tween.sprite) {
+            Some(moved) => {
+                tween.sprite = moved;
+                true
+            }
+            None => false,
+        });
+        self.collision_callbacks.retain_mut(|(a, b, _)| a.remap(new_index) && b.remap(new_index));
+        self.contacts = self.contacts
+            .iter()
+            .filter_map(|&(a, b)| Some((new_index(a)?, new_index(b)?)))
+            .collect();
+        self.events.retain_mut(|event| event.remap(new_index));
+    }
+
+    /// Spin every sprite by its angular velocity over `dt` seconds
+    fn update_rotation(&mut self, dt:f64) {
+        // Keep spinning sprites within one turn so the angle doesn't lose precision over time
+        for sprite in self.sprites_iter_mut().filter(|sprite| sprite.angular_velocity != 0.0) {
+            sprite.rotation = (sprite.rotation + sprite.angular_velocity * dt as f32).rem_euclid(std::f32::consts::TAU);
+        }
+    }
+
+    /// Turn sprites with `auto_face` towards the direction they are moving
+    fn update_facing(&mut self) {
+        for sprite in self.sprites_iter_mut() {
+            if sprite.auto_face {
+                sprite.face_velocity();
+            }
+        }
+    }
+
+    /// Push every sprite by the force fields it overlaps
+    fn apply_force_fields(&mut self) {
+        for sprite in self.sprites.iter_mut() {
+            let bounds = sprite.bounds();
+            for field in &self.force_fields {
+                let overlap = bounds.intersect(&field.rect);
+                if overlap.width > 0 && overlap.height > 0 {
+                    sprite.velocity.0 += field.force.0;
+                    sprite.velocity.1 += field.force.1;
+                }
+            }
+        }
+    }
+
+    // TODO this is bad, completely refactor
+    fn update_physics(&mut self) {
+        // Outside forces act before gravity
+        self.apply_force_fields();
+
+        let friction = if self.on_floor(&self.sprites[0]) {
+            self.physics.ground_friction
+        } else {
+            self.physics.air_friction
+        };
+        let axis = self.fall_axis();
+        let down = self.fall_direction();
+
+        // Move the sprites in smaller increments so fast movement can't skip past the floor.
+        // Every sprite moves with its velocity, only the player feels gravity and the floor.
+        let substeps = self.physics_substeps.max(1);
+        for _ in 0..substeps {
+            let previous = component(self.sprites[0].position, axis);
+            for sprite in self.sprites.iter_mut() {
+                sprite.position.0 += sprite.velocity.0 / 5.0 / substeps as f32;
+                sprite.position.1 += sprite.velocity.1 / 5.0 / substeps as f32;
+            }
+
+            // Land as soon as the movement reaches the floor line, rather than waiting to be inside the floor
+            let landed = if self.physics.swept_floor {
+                self.crossed_floor(&self.sprites[0], previous)
+            } else {
+                component(self.sprites[0].velocity, axis) * down > 0.0 && self.on_floor(&self.sprites[0])
+            };
+
+            if landed {
+                self.land_on_floor(0);
+            }
+        }
+
+        // Wrap around the horizontal edges instead of stopping at them
+        if self.wrap_x {
+            for sprite in self.sprites.iter_mut() {
+                sprite.position.0 = sprite.position.0.rem_euclid(WORLD_WIDTH as f32);
+            }
+        }
+
+        // Define the screen bounds. The wall gravity pulls into is the floor, which landing already handles.
+        let max_x = (WORLD_WIDTH as f32 - self.sprites[0].size.0 as f32).max(0.0);
+        let wall_at = |x:f32| if x <= 0.0 {
+            Some(Side::Left)
+        } else if x >= max_x {
+            Some(Side::Right)
+        } else {
+            None
+        };
+        let floor_side = self.floor_side();
+        let touching = wall_at(self.sprites[0].position.0).filter(|&side| !self.wrap_x && side != floor_side);
+        if let Some(side) = touching {
+            let player = &mut self.sprites[0];
+            let pushed = if side == Side::Left { player.velocity.0 < 0.0 } else { player.velocity.0 > 0.0 };
+            player.position.0 = player.position.0.clamp(0.0, max_x);
+
+            // Only report the wall when the player first runs into it, not every step it's held there
+            if pushed {
+                if self.wall_contact != Some(side) {
+                    self.events.push(EngineEvent::HitBoundary { sprite: 0, side });
+                }
+                player.velocity.0 = 0.0;
+            }
+        }
+        self.wall_contact = touching;
+
+        // Friction slows movement across gravity, down to a stop but never back the other way
+        let across = component_mut(&mut self.sprites[0].velocity, 1 - axis);
+        *across = across.signum() * (across.abs() - friction).max(0.0);
+
+        self.sprites[0].position.0 = (self.sprites[0].position.0 * 100.0).round() / 100.0;
+
+        if !self.on_floor(&self.sprites[0]) {
+            let fall = component_mut(&mut self.sprites[0].velocity, axis);
+            if *fall * down < TERMINAL_VELOCITY {
+                *fall += component(self.physics.gravity, axis).abs() * down;
+            }
+        } else {
+            self.land_on_floor(0);
+        }
+
+        if let Some(bounds) = self.player_bounds {
+            self.clamp_to_bounds(0, bounds);
+        }
+    }
+
+    /// Keep a sprite entirely inside a rectangle of the world, stopping it on any edge it was pushed past
+    fn clamp_to_bounds(&mut self, sprite_index:usize, bounds:Rect) {
+        let sprite = &mut self.sprites[sprite_index];
+        let min = (bounds.x as f32, bounds.y as f32);
+        let max = (
+            (bounds.x as f32 + bounds.width as f32 - sprite.size.0 as f32).max(min.0),
+            (bounds.y as f32 + bounds.height as f32 - sprite.size.1 as f32).max(min.1),
+        );
+
+        let clamped = (sprite.position.0.clamp(min.0, max.0), sprite.position.1.clamp(min.1, max.1));
+        if clamped.0 != sprite.position.0 {
+            sprite.velocity.0 = 0.0;
+        }
+        if clamped.1 != sprite.position.1 {
+            sprite.velocity.1 = 0.0;
+        }
+        sprite.position = clamped;
+    }
+
+    /// The sign of the Y axis pointing towards the floor
+    fn down_direction(&self) -> f32 {
+        match self.coordinate_system {
+            CoordinateSystem::TopLeftYDown => 1.0,
+            CoordinateSystem::BottomLeftYUp => -1.0,
+        }
+    }
+
+    /// The axis gravity pulls along, 0 for X and 1 for Y. Ties go to Y so the floor stays the floor.
+    fn fall_axis(&self) -> usize {
+        if self.physics.gravity.0.abs() > self.physics.gravity.1.abs() { 0 } else { 1 }
+    }
+
+    /// The sign of the fall axis things fall along, away from the floor when gravity points up the screen
+    fn fall_direction(&self) -> f32 {
+        if self.fall_axis() == 0 {
+            self.physics.gravity.0.signum()
+        } else if self.falling_up() {
+            -self.down_direction()
+        } else {
+            self.down_direction()
+        }
+    }
+
+    /// Whether gravity pulls sprites onto the ceiling rather than the floor
+    fn falling_up(&self) -> bool {
+        self.fall_axis() == 1 && self.physics.gravity.1 < 0.0
+    }
+
+    /// The edge of the screen sprites fall onto
+    fn floor_side(&self) -> Side {
+        match (self.fall_axis(), self.fall_direction() > 0.0) {
+            (0, true) => Side::Right,
+            (0, false) => Side::Left,
+            _ if self.falling_up() => Side::Top,
+            _ => Side::Bottom,
+        }
+    }
+
+    /// Whether a sprite is touching or past the floor of the world, or whichever edge gravity pulls it onto
+    fn on_floor(&self, sprite:&Sprite) -> bool {
+        let axis = self.fall_axis();
+        if axis == 0 || self.collision_map.is_some() || self.falling_up() {
+            let down = self.fall_direction();
+            return component(sprite.position, axis) * down >= self.floor_position(sprite) * down;
+        }
+
+        match self.coordinate_system {
+            CoordinateSystem::TopLeftYDown => sprite.collision_y(),
+            CoordinateSystem::BottomLeftYUp => sprite.position.1 <= 0.0,
+        }
+    }
+
+    /// The position along the fall axis a sprite has when resting on the floor, or whichever edge gravity pulls it onto
+    fn floor_position(&self, sprite:&Sprite) -> f32 {
+        if self.fall_axis() == 0 {
+            return if self.fall_direction() > 0.0 { WORLD_WIDTH as f32 - sprite.size.0 as f32 } else { 0.0 };
+        }
+
+        if self.falling_up() {
+            return match self.coordinate_system {
+                CoordinateSystem::TopLeftYDown => 0.0,
+                CoordinateSystem::BottomLeftYUp => WORLD_HEIGHT as f32 - sprite.size.1 as f32,
+            };
+        }
+
+        let flat = match self.coordinate_system {
+            CoordinateSystem::TopLeftYDown => WORLD_HEIGHT as f32 - sprite.size.1 as f32,
+            CoordinateSystem::BottomLeftYUp => 0.0,
+        };
+
+        // Stand on whichever is higher of the world floor and the tile ground under the sprite's center
+        let Some(map) = &self.collision_map else {
+            return flat;
+        };
+        let center_x = sprite.position.0 + sprite.size.0 as f32 / 2.0;
+        let height = WORLD_HEIGHT as f32;
+        match self.coordinate_system {
+            CoordinateSystem::TopLeftYDown => {
+                let feet = sprite.position.1 + sprite.size.1 as f32;
+                map.surface_below(center_x, feet).map_or(flat, |surface| flat.min(surface - sprite.size.1 as f32))
+            }
+            CoordinateSystem::BottomLeftYUp => {
+                let feet = height - sprite.position.1;
+                map.surface_below(center_x, feet).map_or(flat, |surface| flat.max(height - surface))
+            }
+        }
+    }
+
+    /// Whether falling from `previous` along the fall axis to the sprite's current position reached or passed the floor
+    fn crossed_floor(&self, sprite:&Sprite, previous:f32) -> bool {
+        let down = self.fall_direction();
+        let floor = self.floor_position(sprite) * down;
+        let (from, to) = (previous * down, component(sprite.position, self.fall_axis()) * down);
+
+        to > from && to >= floor
+    }
+
+    /// Place a sprite exactly on the floor and stop it falling, bouncing it back off by `restitution`
+    fn land_on_floor(&mut self, sprite_index:usize) {
+        let floor = self.floor_position(&self.sprites[sprite_index]);
+        let axis = self.fall_axis();
+        let down = self.fall_direction();
+        let side = self.floor_side();
+        let physics = self.physics;
+        let sprite = &mut self.sprites[sprite_index];
+        *component_mut(&mut sprite.position, axis) = floor;
+
+        // Only a sprite that was still falling has just hit the floor, one already bouncing keeps rising
+        let velocity = component_mut(&mut sprite.velocity, axis);
+        if *velocity * down > 0.0 {
+            self.events.push(EngineEvent::HitBoundary { sprite: sprite_index, side });
+
+            // Settle once the bounces get smaller than a single step of gravity
+            let bounce = -*velocity * physics.restitution.clamp(0.0, 1.0);
+            *velocity = if bounce.abs() > component(physics.gravity, axis).abs() { bounce } else { 0.0 };
+        }
+    }
+
+    /// Convert a screen pixel to the world position of something `height` pixels tall drawn with its top-left there
+    fn screen_to_world(&self, position:(f32, f32), height:u16) -> (f32, f32) {
+        let x = position.0 + self.camera.0 as i32 as f32;
+        let y = match self.coordinate_system {
+            CoordinateSystem::TopLeftYDown => position.1 + self.camera.1 as i32 as f32,
+            CoordinateSystem::BottomLeftYUp => WORLD_HEIGHT as f32 - position.1 - height as f32 + self.camera.1 as i32 as f32,
+        };
+
+        (x, y)
+    }
+
+    /// Convert a world position to the top-left screen pixel of something `height` pixels tall
+    fn world_to_screen(&self, position:(f32, f32), height:u16, camera:(i32, i32)) -> (i32, i32) {
+        let x = (position.0 as i32).saturating_sub(camera.0);
+        let y = match self.coordinate_system {
+            CoordinateSystem::TopLeftYDown => (position.1 as i32).saturating_sub(camera.1),
+            CoordinateSystem::BottomLeftYUp => (WORLD_HEIGHT as i32)
+                .saturating_sub(position.1 as i32)
+                .saturating_sub(height as i32)
+                .saturating_add(camera.1),
+        };
+
+        (x, y)
+    }
+
+    /// Run every sprite's behavior against the state of the world at the start of the step
+    fn update_behaviors(&mut self, dt:f64) {
+        if self.sprites.iter().all(|sprite| sprite.behavior.is_none()) {
+            return;
+        }
+
+        let context = WorldContext {
+            sprites: self.snapshot().sprites,
+            time: get_current_time(),
+            dt,
+        };
+
+        for i in self.update_order() {
+            let sprite = &mut self.sprites[i];
+
+            // Take the behavior out so it can borrow the sprite mutably
+            if let Some(mut behavior) = sprite.behavior.take() {
+                behavior(sprite, &context);
+                sprite.behavior = Some(behavior);
+            }
+        }
+    }
+
+    /// Move attached sprites to their parent's position plus their offset
+    fn update_attachments(&mut self) {
+        for i in self.update_order() {
+            let parent = match self.sprites[i].parent {
+                Some(parent) if parent != i && parent < self.sprites.len() => parent,
+                _ => continue,
+            };
+
+            let parent_position = self.sprites[parent].position;
+            let parent_width = self.sprites[parent].size.0 as f32;
+            let parent_facing_left = self.sprites[parent].facing_left;
+
+            // Mirror the offset around the parent when it turns around
+            let child = &mut self.sprites[i];
+            let offset_x = if parent_facing_left {
+                parent_width - child.local_offset.0 - child.size.0 as f32
+            } else {
+                child.local_offset.0
+            };
+
+            child.position = (parent_position.0 + offset_x, parent_position.1 + child.local_offset.1);
+            child.facing_left = parent_facing_left;
+        }
+    }
+
+    /// Write a single opaque pixel to the frame, ignoring anything outside the world or clip
+    fn put_pixel(&self, frame: &mut [u8], x:i32, y:i32, color:[u8; 4]) {
+        let (width, height) = self.render_size();
+        if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 || !self.in_clip(x, y) {
+            return;
+        }
+
+        let index = ((y as usize * width as usize) + x as usize) * 4;
+        frame[index..index + 4].copy_from_slice(&color);
+    }
+
+    /// Alpha blend a single pixel onto the frame, ignoring anything outside the world or clip
+    fn blend_pixel(&self, frame: &mut [u8], x:i32, y:i32, color:[u8; 4]) {
+        self.blend_pixel_with(frame, x, y, color, false);
+    }
+
+    /// Alpha blend a single pixel, treating its color as already multiplied by alpha if `premultiplied` is set
+    fn blend_pixel_with(&self, frame: &mut [u8], x:i32, y:i32, color:[u8; 4], premultiplied:bool) {
+        let (width, height) = self.render_size();
+        if color[3] == 0 || x < 0 || y < 0 || x >= width as i32 || y >= height as i32 || !self.in_clip(x, y) {
+            return;
+        }
+
+        let index = ((y as usize * width as usize) + x as usize) * 4;
+        let world_pixel = &mut frame[index..index + 4];
+        let alpha = color[3] as f32 / 255.0;
+
+        for c in 0..3 {
+            let foreground = if premultiplied {
+                color[c]
+            } else {
+                (color[c] as f32 * alpha) as u8
+            };
+
+            world_pixel[c] = ((world_pixel[c] as f32 * (1.0 - alpha)) as u8).saturating_add(foreground);
+        }
+        world_pixel[3] = 255;
+    }
+
+    /// Fill a rectangle by repeating a texture across it, clipped to the world
+    #[allow(dead_code)]
+    fn draw_tiled(&self, frame: &mut [u8], image:&DynamicImage, x:i32, y:i32, width:u32, height:u32) {
+        let (tile_width, tile_height) = image.dimensions();
+        if tile_width == 0 || tile_height == 0 {
+            return;
+        }
+
+        for ty in 0..height {
+            for tx in 0..width {
+                let color = image.get_pixel(tx % tile_width, ty % tile_height).0;
+                self.blend_pixel(frame, x + tx as i32, y + ty as i32, color);
+            }
+        }
+    }
+
+    /// Draw a 1px outline of a rectangle to the frame, clipped to the world
+    fn draw_rect(&self, frame: &mut [u8], rect:Rect, color:[u8; 4]) {
+        if rect.width == 0 || rect.height == 0 {
+            return;
+        }
+
+        let right = rect.x + rect.width as i32 - 1;
+        let bottom = rect.y + rect.height as i32 - 1;
+
+        for x in rect.x..=right {
+            self.put_pixel(frame, x, rect.y, color);
+            self.put_pixel(frame, x, bottom, color);
+        }
+
+        for y in rect.y..=bottom {
+            self.put_pixel(frame, rect.x, y, color);
+            self.put_pixel(frame, right, y, color);
+        }
+    }
+
+    /// Y coordinate of the floor line that sprites stand on
+    fn floor_line(&self) -> f32 {
+        match self.coordinate_system {
+            CoordinateSystem::TopLeftYDown => WORLD_HEIGHT as f32,
+            CoordinateSystem::BottomLeftYUp => 0.0,
+        }
+    }
+
+    /// The output pixel a sprite's shadow is centered on and its radii, `None` for a sprite with no width
+    fn shadow_ellipse(&self, sprite:&Sprite, camera:(i32, i32)) -> Option<((i32, i32), (f32, f32))> {
+        let render_scale = self.render_scale.max(1) as f32;
+        let (min_x, _, max_x, _) = sprite.rendered_aabb();
+        let center = min_x + (max_x - min_x) / 2.0;
+        let radius_x = (max_x - min_x) / 2.0 * render_scale;
+        let radius_y = (radius_x / 4.0).max(1.0);
+        if radius_x <= 0.0 {
+            return None;
+        }
+
+        Some((self.world_to_render((center, self.floor_line()), 0, camera), (radius_x, radius_y)))
+    }
+
+    /// The output pixels a sprite's shadow can cover
+    fn shadow_rect(&self, sprite:&Sprite, camera:(i32, i32)) -> Rect {
+        let Some(((center_x, center_y), (radius_x, radius_y))) = self.shadow_ellipse(sprite, camera) else {
+            return Rect::new(0, 0, 0, 0);
+        };
+
+        let (reach_x, reach_y) = (radius_x.ceil() as i32, radius_y.ceil() as i32);
+        Rect::new(center_x - reach_x, center_y - reach_y, reach_x as u32 * 2 + 1, reach_y as u32 * 2 + 1)
+    }
+
+    /// Draw a flat translucent ellipse on the floor centered under the sprite, as wide as the sprite
+    fn draw_shadow(&self, frame: &mut [u8], sprite:&Sprite, camera:(i32, i32)) {
+        let Some(((center_x, center_y), (radius_x, radius_y))) = self.shadow_ellipse(sprite, camera) else {
+            return;
+        };
+
+        let (reach_x, reach_y) = (radius_x.ceil() as i32, radius_y.ceil() as i32);
+        for y in -reach_y..=reach_y {
+            for x in -reach_x..=reach_x {
+                let (nx, ny) = ((x as f32 + 0.5) / radius_x, (y as f32 + 0.5) / radius_y);
+                if nx * nx + ny * ny <= 1.0 {
+                    self.blend_pixel(frame, center_x + x, center_y + y, SHADOW_COLOR);
+                }
+            }
+        }
+    }
+
+    /// The output pixel a sprite is drawn from. Above a render scale of 1 the part of the position
+    /// between world pixels shifts it by whole output pixels, unless the sprite snaps to the world grid.
+    fn sprite_origin(&self, sprite:&Sprite, camera:(i32, i32)) -> (i32, i32) {
+        let (_, height) = sprite.scaled_size();
+        if sprite.pixel_snap {
+            let snapped = (sprite.position.0.floor(), sprite.position.1.floor());
+            return self.world_to_render(snapped, height, camera);
+        }
+
+        let scale = self.render_scale.max(1) as f32;
+        let (x, y) = self.world_to_render(sprite.position, height, camera);
+        let fraction = (sprite.position.0.fract() * scale, sprite.position.1.fract() * scale * self.down_direction());
+        (x.saturating_add(fraction.0.floor() as i32), y.saturating_add(fraction.1.floor() as i32))
+    }
+
+    /// The box the floor and walls stop a sprite at, its unscaled and unrotated size at its position, in output pixels
+    fn screen_body_box(&self, sprite:&Sprite, camera:(i32, i32)) -> Rect {
+        let scale = self.render_scale.max(1);
+        let (x, y) = self.world_to_render(sprite.position, sprite.size.1, camera);
+        Rect::new(x, y, sprite.size.0 as u32 * scale, sprite.size.1 as u32 * scale)
+    }
+
+    /// The rendered bounds of a sprite in output pixels
+    fn screen_bounds(&self, sprite:&Sprite, camera:(i32, i32)) -> Rect {
+        let origin = self.sprite_origin(sprite, camera);
+        let (min_x, min_y, max_x, max_y) = sprite.local_aabb_at(self.render_scale.max(1) as f32);
+        Rect::new(
+            origin.0.saturating_add(min_x.floor() as i32),
+            origin.1.saturating_add(min_y.floor() as i32),
+            (max_x.ceil() - min_x.floor()) as u32,
+            (max_y.ceil() - min_y.floor()) as u32,
+        )
+    }
+
+    /// The output pixels drawing a sprite can touch, its bounds with room for an outline,
+    /// stretched across the whole width when the world wraps
+    fn drawn_rect(&self, sprite:&Sprite, camera:(i32, i32)) -> Rect {
+        let bounds = self.screen_bounds(sprite, camera);
+        let mut rect = Rect::new(bounds.x.saturating_sub(1), bounds.y.saturating_sub(1), bounds.width + 2, bounds.height + 2);
+        if self.wrap_x {
+            rect.x = 0;
+            rect.width = self.render_size().0;
+        }
+        rect
+    }
+
+    /// Everything the world's sprites, their shadows and debug boxes can draw over the background
+    fn sprite_overlay(&self, camera:(i32, i32)) -> Rect {
+        self.sprites_iter().fold(Rect::new(0, 0, 0, 0), |overlay, sprite| {
+            let mut overlay = overlay.union(&self.drawn_rect(sprite, camera));
+            if sprite.cast_shadow {
+                overlay = overlay.union(&self.shadow_rect(sprite, camera));
+            }
+            if self.debug_bounds {
+                overlay = overlay.union(&self.screen_body_box(sprite, camera));
+            }
+            overlay
+        })
+    }
+
+    /// Whether any part of the sprite lands inside the viewport
+    fn on_screen(&self, sprite:&Sprite, camera:(i32, i32)) -> bool {
+        let mut bounds = self.screen_bounds(sprite, camera);
+
+        // The outline sits just outside the sprite
+        if sprite.outline.is_some() {
+            bounds = Rect::new(bounds.x.saturating_sub(1), bounds.y.saturating_sub(1), bounds.width + 2, bounds.height + 2);
+        }
+
+        // Horizontal wrapping can bring any sprite back around, so only the vertical span matters
+        if self.wrap_x {
+            bounds.x = 0;
+            bounds.width = bounds.width.max(1);
+        }
+
+        let (width, height) = self.render_size();
+        let viewport = Rect::new(0, 0, width, height);
+        let visible = bounds.intersect(&viewport);
+        visible.width > 0 && visible.height > 0
+    }
+
+    /// Draw a single sprite to the frame buffer, offset by the given camera position
+    fn draw_sprite(&self, frame: &mut [u8], sprite:&Sprite, camera:(i32, i32)) {
+        let mut layer = self.layer(sprite.layer);
+        layer.opacity *= sprite.opacity.clamp(0.0, 1.0);
+        if !layer.visible || layer.opacity <= 0.0 {
+            return;
+        }
+
+        let render_scale = self.render_scale.max(1) as f32;
+        let render_width = self.render_size().0 as i32;
+        let origin = self.sprite_origin(sprite, camera);
+        let (min_x, min_y, max_x, max_y) = sprite.local_aabb_at(render_scale);
+        let sample = sprite.sampler(render_scale);
+
+        // Saturate instead of wrapping so far away sprites can't produce a bogus index,
+        // and draw the part of the sprite hanging over the seam on the other side
+        let to_viewport = |x:i32, y:i32| {
+            let viewport_x = origin.0.saturating_add(x);
+            let viewport_x = if self.wrap_x { viewport_x.rem_euclid(render_width) } else { viewport_x };
+            (viewport_x, origin.1.saturating_add(y))
+        };
+
+        // Ring the visible pixels with the outline color, one pixel outside the sprite
+        if let Some(outline) = sprite.outline {
+            for y in min_y.floor() as i32 - 1..max_y.ceil() as i32 + 1 {
+                for x in min_x.floor() as i32 - 1..max_x.ceil() as i32 + 1 {
+                    let touches_sprite = [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+                        .iter()
+                        .any(|&(nx, ny)| sample(nx, ny).is_some());
+                    if sample(x, y).is_none() && touches_sprite {
+                        let (viewport_x, viewport_y) = to_viewport(x, y);
+                        self.blend_pixel(frame, viewport_x, viewport_y, fade_color(outline, layer.opacity, false));
+                    }
+                }
+            }
+        }
+
+        // Loop through all pixels the rotated sprite covers on screen
+        for y in min_y.floor() as i32..max_y.ceil() as i32 {
+            for x in min_x.floor() as i32..max_x.ceil() as i32 {
+                let Some(colors) = sample(x, y) else {
+                    continue;
+                };
+                let (viewport_x, viewport_y) = to_viewport(x, y);
+
+                // Draw onto the frame, skipping anything outside the world. A faded layer always blends
+                let premultiplied = sprite.sprite_sheet.premultiplied;
+                match sprite.sprite_sheet.alpha_mode {
+                    _ if layer.opacity < 1.0 => {
+                        let color = fade_color(colors.0, layer.opacity, premultiplied);
+                        self.blend_pixel_with(frame, viewport_x, viewport_y, color, premultiplied);
+                    }
+                    AlphaMode::Blend => {
+                        self.blend_pixel_with(frame, viewport_x, viewport_y, colors.0, premultiplied);
+                    }
+                    AlphaMode::Clip(_) => {
+                        let [r, g, b, _] = colors.0;
+                        self.put_pixel(frame, viewport_x, viewport_y, [r, g, b, 255]);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Map a screen pixel to the background pixel under it, repeating the background as the camera scrolls
+    fn background_coords(&self, x:i32, y:i32) -> (u32, u32) {
+        let (width, height) = self.background_image.dimensions();
+        (
+            (x + self.camera.0 as i32).rem_euclid(width as i32) as u32,
+            (y + self.camera.1 as i32).rem_euclid(height as i32) as u32,
+        )
+    }
+
+    /// The background color under a screen pixel, filtered with `background_sampling`
+    fn sample_background(&self, x:i32, y:i32) -> [u8; 4] {
+        if let Background::AnimatedTile(sheet) = &self.background {
+            return self.sample_tile(sheet, x, y);
+        }
+
+        match self.background_sampling {
+            BackgroundSampling::Nearest => {
+                let (background_x, background_y) = self.background_coords(x, y);
+                self.background_image.get_pixel(background_x, background_y).0
+            }
+            BackgroundSampling::Bilinear => {
+                let (width, height) = self.background_image.dimensions();
+                let (camera_x, camera_y) = (self.camera.0.floor(), self.camera.1.floor());
+                let (fraction_x, fraction_y) = (self.camera.0 - camera_x, self.camera.1 - camera_y);
+                let left = (x + camera_x as i32).rem_euclid(width as i32) as u32;
+                let top = (y + camera_y as i32).rem_euclid(height as i32) as u32;
+                let right = (left + 1) % width;
+                let bottom = (top + 1) % height;
+
+                let texel = |tx, ty| self.background_image.get_pixel(tx, ty).0;
+                let (top_left, top_right) = (texel(left, top), texel(right, top));
+                let (bottom_left, bottom_right) = (texel(left, bottom), texel(right, bottom));
+
+                let mut rgba = [0; 4];
+                for (c, channel) in rgba.iter_mut().enumerate() {
+                    let upper = top_left[c] as f32 + (top_right[c] as f32 - top_left[c] as f32) * fraction_x;
+                    let lower = bottom_left[c] as f32 + (bottom_right[c] as f32 - bottom_left[c] as f32) * fraction_x;
+                    *channel = (upper + (lower - upper) * fraction_y).round() as u8;
+                }
+                rgba
+            }
+        }
+    }
+
+    /// The pixel of an animated tile's current frame under a screen pixel, tiling it across the world
+    fn sample_tile(&self, sheet:&SpriteSheet, x:i32, y:i32) -> [u8; 4] {
+        let animation = &sheet.animations[sheet.current_animation];
+        let (frame_width, frame_height) = animation.current_frame_size().unwrap_or(sheet.frame_size);
+        if frame_width == 0 || frame_height == 0 {
+            return [0, 0, 0, 255];
+        }
+
+        let tile_x = (x + self.camera.0 as i32).rem_euclid(frame_width as i32) as u32;
+        let tile_y = (y + self.camera.1 as i32).rem_euclid(frame_height as i32) as u32;
+        let (sample_x, sample_y) = (animation.current_position.0 as u32 + tile_x, animation.current_position.1 as u32 + tile_y);
+        if sample_x >= sheet.texture.width() || sample_y >= sheet.texture.height() {
+            return [0, 0, 0, 255];
+        }
+        sheet.texture.get_pixel(sample_x, sample_y).0
+    }
+
+    /// Update all sprite frames
+    fn update_sprite_animations(&mut self) {
+        let current_time = self.tick_animation_clock();
+        if self.animations_paused {
+            return;
+        }
+
+        if let Background::AnimatedTile(sheet) = &mut self.background {
+            let (frame_size, frame_spacing, sheet_dimensions) = (sheet.frame_size, sheet.frame_spacing, sheet.sheet_dimensions);
+            let current = sheet.current_animation;
+            sheet.animations[current].increment_frame(frame_size, frame_spacing, sheet_dimensions, current_time);
+        }
+
+        for (i, sprite) in self.sprites.iter_mut().enumerate() {
+            let was_finished = sprite.animation_finished();
+            sprite.run_animation(current_time);
+            if !was_finished && sprite.animation_finished() {
+                self.events.push(EngineEvent::AnimationFinished { sprite: i });
+            }
+        }
+
+        for sprite in self.ui_sprites.iter_mut() {
+            sprite.run_animation(current_time);
+        }
+
+        if let Some(cursor) = &mut self.cursor_sprite {
+            cursor.run_animation(current_time);
+        }
+    }
+
+    /// The animation clock's current time without advancing it. A wall clock that hasn't been ticked
+    /// yet starts from the current time on its first tick, so that is reported instead.
+    #[allow(dead_code)]
+    fn animation_time(&self) -> f64 {
+        match (self.clock_mode, self.last_clock_update) {
+            (ClockMode::WallClock, None) => get_current_time() as f64,
+            _ => self.animation_clock,
+        }
+    }
+
+    /// Advance the animation clock by the real time since it was last checked, or by one tick in
+    /// `ClockMode::Ticks`, scaled by `time_scale`. Returns the animation time in milliseconds or ticks.
+    fn tick_animation_clock(&mut self) -> i128 {
+        let now = get_current_time();
+        let time_scale = self.time_scale.max(0.0) as f64;
+        match (self.clock_mode, self.last_clock_update) {
+            // Stop the clock while paused so animations resume where they left off
+            _ if self.animations_paused => {}
+            (ClockMode::Ticks, _) => self.animation_clock += time_scale,
+            (ClockMode::WallClock, Some(last)) => self.animation_clock += (now - last) as f64 * time_scale,
+            (ClockMode::WallClock, None) => self.animation_clock = now as f64,
+        }
+        self.last_clock_update = Some(now);
+
+        self.animation_clock as i128
+    }
+
+    /// Step and draw `frames` frames without a window, timing each one
+    #[allow(dead_code)]
+    fn benchmark(&mut self, frames:usize) -> Result<BenchReport, Box<dyn std::error::Error>> {
+        let (width, height) = self.render_size();
+        let mut frame = vec![0; (width * height * 4) as usize];
+        let mut report = BenchReport {
+            frames,
+            total: Duration::ZERO,
+            avg: Duration::ZERO,
+            max: Duration::ZERO,
+        };
+
+        for _ in 0..frames {
+            let start = Instant::now();
+            self.step(self.target_frame_time);
+            self.render(&mut frame)?;
+            let elapsed = start.elapsed();
+
+            report.total += elapsed;
+            report.max = report.max.max(elapsed);
+        }
+
+        if frames > 0 {
+            report.avg = report.total / frames as u32;
+        }
+        Ok(report)
+    }
+
+    /// Draw the world into a render target and present the result
+    fn render(&mut self, target: &mut impl RenderTarget) -> Result<(), Box<dyn std::error::Error>> {
+        self.draw(target.frame_mut())?;
+        target.present()
+    }
+
+    /// The RGBA color a frame drawn by this world shows at a world position, if that position is on screen.
+    /// Camera zoom isn't taken into account.
+    #[allow(dead_code)]
+    fn pixel_at(&self, frame:&[u8], x:u32, y:u32) -> Option<[u8; 4]> {
+        let (width, height) = self.render_size();
+        let camera = (self.camera.0 as i32, self.camera.1 as i32);
+        let (screen_x, screen_y) = self.world_to_render((x as f32, y as f32), 1, camera);
+        if screen_x < 0 || screen_y < 0 || screen_x as u32 >= width || screen_y as u32 >= height {
+            return None;
+        }
+
+        let index = (screen_y as usize * width as usize + screen_x as usize) * 4;
+        let mut pixel:[u8; 4] = frame.get(index..index + 4)?.try_into().ok()?;
+        if self.pixel_format == PixelFormat::Bgra8 {
+            pixel.swap(0, 2);
+        }
+
+        Some(pixel)
+    }
+
+    /// Blend the previous blurred frame into the new one by `motion_blur`, leaving trails behind moving sprites
+    fn apply_motion_blur(&mut self, frame: &mut [u8]) {
+        let weight = self.motion_blur.clamp(0.0, 1.0);
+        if weight <= 0.0 {
+            self.motion_blur_frame = None;
+            return;
+        }
+
+        if let Some(previous) = self.motion_blur_frame.as_mut().filter(|previous| previous.len() == frame.len()) {
+            for (value, previous) in frame.iter_mut().zip(previous.iter_mut()) {
+                *value = (*value as f32 * (1.0 - weight) + *previous as f32 * weight).round() as u8;
+                *previous = *value;
+            }
+        } else {
+            self.motion_blur_frame = Some(frame.to_vec());
+        }
+    }
+
+    /// Turn a frame drawn by this world back into an RGBA image, e.g. for screenshots
+    fn capture_frame(&self, frame:&[u8]) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+        let (width, height) = self.render_size();
+        let mut pixels = frame.to_vec();
+        if self.pixel_format == PixelFormat::Bgra8 {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        let image = RgbaImage::from_raw(width, height, pixels).ok_or_else(|| format!(
+            "frame buffer is {} bytes but a {width}x{height} world needs {} bytes",
+            frame.len(),
+            width * height * 4,
+        ))?;
+        Ok(DynamicImage::ImageRgba8(image))
+    }
+
+    /// Fill everything in a frame buffer `full_width` pixels wide outside of `region` with the letterbox color
+    #[allow(dead_code)]
+    fn fill_letterbox(&self, frame: &mut [u8], full_width:u32, region:Rect) {
+        if full_width == 0 {
+            return;
+        }
+
+        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+            let (x, y) = ((i % full_width as usize) as i32, (i / full_width as usize) as i32);
+            if !region.contains(x, y) {
+                pixel.copy_from_slice(&self.letterbox_color);
+            }
+        }
+    }
+
+    /// The letterbox color as the clear color of the window surface
+    fn letterbox_clear_color(&self) -> pixels::wgpu::Color {
+        let [r, g, b, a] = self.letterbox_color.map(|channel| channel as f64 / 255.0);
+        pixels::wgpu::Color { r, g, b, a }
+    }
+
+    /// Draw the background, sprites and debug bounds from the current camera, within the current clip
+    fn draw_world(&mut self, frame: &mut [u8]) {
+        // Draw the background, copying it from the cache when a static background is still valid
+        let cache_valid = self.background_cache.as_ref().is_some_and(|cache| {
+            cache.camera == self.camera
+                && cache.tint == self.background_tint
+                && cache.sampling == self.background_sampling
+                && cache.render_scale == self.render_scale
+                && cache.pixels.len() == frame.len()
+                && matches!(self.background, Background::Image)
+                && self.clip_stack.is_empty()
+        });
+        let camera = (self.camera.0 as i32, self.camera.1 as i32);
+        let overlay = self.sprite_overlay(camera);
+        let width = self.render_size().0;
+        let use_cache = self.static_background && cache_valid;
+        match &mut self.background_cache {
+            Some(cache) if use_cache => {
+                // Only what was drawn over the background last frame, or is about to be, needs copying back
+                match cache.overlay {
+                    Some(previous) => cache.restore(frame, width, previous.union(&overlay)),
+                    None => frame.copy_from_slice(&cache.pixels),
+                }
+                cache.overlay = Some(overlay);
+            }
+            _ => {
+                self.paint_background(frame);
+                let cacheable = self.static_background
+                    && matches!(self.background, Background::Image)
+                    && self.clip_stack.is_empty();
+                self.background_cache = cacheable.then(|| BackgroundCache {
+                    pixels: frame.to_vec(),
+                    camera: self.camera,
+                    tint: self.background_tint,
+                    sampling: self.background_sampling,
+                    render_scale: self.render_scale,
+                    overlay: Some(overlay),
+                });
+            }
+        }
+
+        // Draw sprites on the background, offset by the camera, skipping any that can't be seen
+        let mut culled = 0;
+        for sprite in self.draw_order().into_iter().map(|i| &self.sprites[i]) {
+            // Shadows land on the floor even when the sprite itself is out of view
+            if sprite.cast_shadow && self.layer(sprite.layer).visible {
+                self.draw_shadow(frame, sprite, camera);
+            }
+
+            if !self.on_screen(sprite, camera) {
+                culled += 1;
+                continue;
+            }
+            self.draw_sprite(frame, sprite, camera);
+        }
+        self.culled_sprites = culled;
+
+        // Outline each sprite's bounding box on top of the world
+        if self.debug_bounds {
+            for sprite in self.sprites_iter() {
+                let bounds = self.screen_bounds(sprite, camera);
+                self.draw_rect(frame, bounds, DEBUG_BOUNDS_COLOR);
+
+                // Sprites collide with each other by their bounds, but the floor and walls only know
+                // their unscaled size, which scaling and rotation move away from the drawn box
+                let body = self.screen_body_box(sprite, camera);
+                if body != bounds {
+                    self.draw_rect(frame, body, DEBUG_BODY_COLOR);
+                }
+            }
+        }
+    }
+
+    /// Draw the world into each viewport from its own camera, centering any sprite it follows
+    fn draw_viewports(&mut self, frame: &mut [u8]) {
+        let main_camera = self.camera;
+        let shake = self.screen_shake.map_or((0.0, 0.0), |shake| shake.offset());
+
+        for i in 0..self.viewports.len() {
+            let camera = self.viewport_camera(&self.viewports[i]);
+            self.viewports[i].camera = camera;
+
+            self.camera = (camera.0 + shake.0, camera.1 + shake.1);
+            self.push_clip(self.viewports[i].rect);
+            self.draw_world(frame);
+            self.pop_clip();
+        }
+
+        self.camera = main_camera;
+    }
+
+    /// The camera that centers a viewport on the sprite it follows, or its own camera if it follows nothing
+    fn viewport_camera(&self, viewport:&Viewport) -> (f32, f32) {
+        let Some(sprite) = viewport.follow_sprite.and_then(|i| self.sprites.get(i)) else {
+            return viewport.camera;
+        };
+
+        let scale = self.render_scale.max(1) as f32;
+        let (min_x, min_y, max_x, max_y) = sprite.rendered_aabb();
+        let center = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+        let screen_center = (
+            (viewport.rect.x as f32 + viewport.rect.width as f32 / 2.0) / scale,
+            (viewport.rect.y as f32 + viewport.rect.height as f32 / 2.0) / scale,
+        );
+
+        let x = center.0 - screen_center.0;
+        let y = match self.coordinate_system {
+            CoordinateSystem::TopLeftYDown => center.1 - screen_center.1,
+            CoordinateSystem::BottomLeftYUp => screen_center.1 - WORLD_HEIGHT as f32 + center.1,
+        };
+
+        (x, y)
+    }
+
+    /// Draw the world into a sub-rectangle of a larger frame buffer `full_width` pixels wide,
+    /// scaling it to fit the region and leaving everything outside the region untouched
+    #[allow(dead_code)]
+    fn draw_into(&mut self, frame: &mut [u8], full_width:u32, region:Rect) -> Result<(), Box<dyn std::error::Error>> {
+        let row_len = full_width as usize * 4;
+        if row_len == 0 || !frame.len().is_multiple_of(row_len) {
+            return Err(format!("frame buffer of {} bytes is not made of {full_width} pixel rows", frame.len()).into());
+        }
+        let full_height = (frame.len() / row_len) as u32;
+
+        let (width, height) = self.render_size();
+        let mut world_frame = vec![0; (width * height * 4) as usize];
+
+        // A fresh buffer holds nothing of the last frame, so a static background has to be copied in whole
+        if let Some(cache) = &mut self.background_cache {
+            cache.overlay = None;
+        }
+        self.draw(&mut world_frame)?;
+
+        // Only touch the part of the region that is actually inside the frame
+        let visible = region.intersect(&Rect::new(0, 0, full_width, full_height));
+        for y in visible.y..visible.y + visible.height as i32 {
+            let world_y = (y - region.y) as u64 * height as u64 / region.height as u64;
+
+            for x in visible.x..visible.x + visible.width as i32 {
+                let world_x = (x - region.x) as u64 * width as u64 / region.width as u64;
+
+                let source = ((world_y * width as u64 + world_x) * 4) as usize;
+                let destination = (y as usize * full_width as usize + x as usize) * 4;
+                frame[destination..destination + 4].copy_from_slice(&world_frame[source..source + 4]);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Draw the world and return the smallest rectangle holding every pixel that differs from the
+    /// previous call, `None` if nothing changed. The first call reports the whole frame.
+    #[allow(dead_code)]
+    fn draw_with_dirty(&mut self, frame: &mut [u8]) -> Result<Option<Rect>, Box<dyn std::error::Error>> {
+        self.draw(frame)?;
+
+        let (width, height) = self.render_size();
+        let dirty = match &self.previous_frame {
+            Some(previous) if previous.len() == frame.len() => {
+                // Grow the bounds around every pixel that changed
+                let mut bounds = Rect::new(0, 0, 0, 0);
+                for (i, (new, old)) in frame.chunks_exact(4).zip(previous.chunks_exact(4)).enumerate() {
+                    if new != old {
+                        bounds = bounds.union(&Rect::new((i as u32 % width) as i32, (i as u32 / width) as i32, 1, 1));
+                    }
+                }
+
+                Some(bounds).filter(|bounds| bounds.width > 0)
+            }
+            _ => Some(Rect::new(0, 0, width, height)),
+        };
+
+        match &mut self.previous_frame {
+            Some(previous) if previous.len() == frame.len() => previous.copy_from_slice(frame),
+            previous => *previous = Some(frame.to_vec()),
+        }
+        Ok(dirty)
+    }
+
+    /// Sample and tint the background for every pixel of the frame, in world pixels
+    fn paint_background(&self, frame: &mut [u8]) {
+        let width = self.render_size().0 as usize;
+        let render_scale = self.render_scale.max(1) as usize;
+        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+            if !self.in_clip((i % width) as i32, (i / width) as i32) {
+                continue;
+            }
+
+            let x = i % width / render_scale;
+            let y = i / width / render_scale;
+
+            let mut rgba = self.sample_background(x as i32, y as i32);
+
+            // Tint the background before any sprites are blended on top
+            for (channel, tint) in rgba.iter_mut().zip(self.background_tint) {
+                *channel = (*channel as f32 * tint).clamp(0.0, 255.0) as u8;
+            }
+
+            pixel.copy_from_slice(&rgba);
+        }
+    }
+
+    /// Draw the updated state of all sprites and background to the frame buffer.
+    fn draw(&mut self, frame: &mut [u8]) -> Result<(), Box<dyn std::error::Error>> {
+        // Refuse to draw into a buffer that doesn't match the world, e.g. after a resize race
+        let (width, height) = self.render_size();
+        let expected_len = (width * height * 4) as usize;
+        if frame.len() != expected_len {
+            return Err(format!(
+                "frame buffer is {} bytes but a {}x{} world needs {} bytes",
+                frame.len(),
+                width,
+                height,
+                expected_len,
+            ).into());
+        }
+
+        // Pick up any changed asset files every so often
+        if self.hot_reload && get_current_time() - self.last_reload_check >= HOT_RELOAD_INTERVAL {
+            self.reload_changed_assets();
+            self.last_reload_check = get_current_time();
+        }
+
+        // Run any animation updates
+        self.update_sprite_animations();
+        self.update_cursor();
+
+        // Shake the camera for this frame only
+        let unshaken_camera = self.camera;
+        if let Some(shake) = self.screen_shake {
+            let (x, y) = shake.offset();
+            self.camera = (self.camera.0 + x, self.camera.1 + y);
+        }
+
+        // Draw the world through each viewport, or across the whole frame from the main camera
+        if self.viewports.is_empty() {
+            self.draw_world(frame);
+        } else {
+            self.draw_viewports(frame);
+        }
+        let camera = (self.camera.0 as i32, self.camera.1 as i32);
+
+        self.apply_zoom(frame);
+
+        // Draw the UI on top of the world, ignoring the camera
+        for sprite in &self.ui_sprites {
+            self.draw_sprite(frame, sprite, self.ui_camera(sprite));
+        }
+
+        // Draw the cursor over everything at the mouse position
+        if let (Some(cursor), Some(_)) = (&self.cursor_sprite, self.mouse_position) {
+            self.draw_sprite(frame, cursor, camera);
+        }
+
+        self.draw_fade(frame);
+        self.apply_motion_blur(frame);
+        self.camera = unshaken_camera;
+
+        // The static background is restored under the UI and cursor next frame too, unless something
+        // changed every pixel and there's no telling what's left of it
+        let whole_frame = self.camera_zoom > 1.0
+            || self.fade.is_some()
+            || self.motion_blur > 0.0
+            || self.pixel_format == PixelFormat::Bgra8;
+        let ui = self.ui_sprites
+            .iter()
+            .map(|sprite| self.drawn_rect(sprite, self.ui_camera(sprite)))
+            .chain(self.cursor_sprite.as_ref().filter(|_| self.mouse_position.is_some()).map(|cursor| self.drawn_rect(cursor, camera)))
+            .fold(Rect::new(0, 0, 0, 0), |overlay, rect| overlay.union(&rect));
+        if let Some(cache) = &mut self.background_cache {
+            cache.overlay = cache.overlay.filter(|_| !whole_frame).map(|overlay| overlay.union(&ui));
+        }
+
+        // Everything above works in RGBA, reorder the channels for the output surface
+        if self.pixel_format == PixelFormat::Bgra8 {
+            for pixel in frame.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn main() -> Result<(), Error> {
+    env_logger::init();
+    let event_loop = EventLoop::new();
+    let mut input = WinitInputHelper::new();
+    let input_map = InputMap::from_file(Path::new("controls.ron")).unwrap_or_else(|err| {
+        warn!("{err}, using the default controls");
+        InputMap::default()
+    });
+    // Pick the biggest pixel perfect window that fits on the screen
+    let window_scale = event_loop.primary_monitor().map_or(WINDOW_SCALE, |monitor| {
+        let screen:LogicalSize<u32> = monitor.size().to_logical(monitor.scale_factor());
+        integer_scale((screen.width, screen.height), (WORLD_WIDTH, WORLD_HEIGHT)).min(WINDOW_SCALE)
+    });
+    let window = {
+        let size = window_logical_size(window_scale);
+        WindowBuilder::new()
+            .with_title("2D Rendering Test")
+            .with_inner_size(size)
+            .with_min_inner_size(size)
+            .with_max_inner_size(size)
+            .build(&event_loop)
+            .unwrap()
+    };
+
+    let player_idle = Animation::from_row(0, 0, 4, (50, 37), 200).named("idle");
+    let player_slide = Animation::from_row(2, 0, 4, (50, 37), 100).named("slide");
+    let player_animations = vec![player_idle, player_slide];
+    let player_sheet = SpriteSheet::load(Path::new("assets/images/player_sheet.png"), player_animations, (50, 37)).unwrap();
+    let mut player = Sprite::new(player_sheet);
+    player.auto_face = true;
+
+    let window_static = Animation::new((0, 0), 1, 0);
+    let window_animations = vec![window_static];
+    let window_sheet = SpriteSheet::load(Path::new("assets/images/building.png"), window_animations, (98, 72)).unwrap();
+    let mut window_sprite = Sprite::new(window_sheet);
+
+    window_sprite.position = (100.0, (WORLD_HEIGHT - window_sprite.size.1 as u32) as f32);
+
+    let sprite_list: Vec<Sprite> = vec![player, window_sprite];
+
+    let mut world = World::new(sprite_list);
+
+    // The surface is always sized in physical pixels, which differ from logical ones on high DPI displays
+    let window_size = physical_size(window_logical_size(window_scale), window.scale_factor());
+    let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
+
+    let (render_width, render_height) = world.render_size();
+    let mut pixels = PixelsBuilder::new(render_width, render_height, surface_texture)
+    .enable_vsync(true)
+    .clear_color(world.letterbox_clear_color())
+    .build()?;
+
+    // Hide the OS cursor when the world draws its own
+    window.set_cursor_visible(world.cursor_sprite.is_none());
+    let mut last_update = Instant::now();
+    let mut last_frame = Instant::now();
+
+    event_loop.run(move |event, _, control_flow| {
+
+        // Draw the current frame
+        if let Event::RedrawRequested(_) = event {
+            let now = Instant::now();
+            world.record_frame_time((now - last_frame).as_secs_f64());
+            last_frame = now;
+
+            if let Err(err) = world.render(&mut pixels) {
+                error!("world.render() failed: {err}");
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+        }
+
+        // Handle input events
+        if input.update(&event) {
+            // Close events
+            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+                *control_flow = ControlFlow::Exit;
+            }
+
+            for action in input_map.pressed_actions(&input) {
+                world.record_press(action);
+            }
+
+            // Toggle the debug bounding boxes
+            if input_map.pressed(&input, Action::ToggleDebug) {
+                world.set_debug_bounds(!world.debug_bounds);
+            }
+
+            if input_map.pressed(&input, Action::Jump) {
+                world.jump();
+                world.set_sprite_animation(0, 0);
+            } else if input_map.released(&input, Action::Jump) {
+                world.set_sprite_animation(0, 0);
+            }
+
+            // Pause and unpause the game
+            if input_map.pressed(&input, Action::Pause) {
+                world.toggle_pause();
+            }
+
+            // Toggle the level editor camera
+            if input_map.pressed(&input, Action::ToggleEditor) {
+                world.set_editor_mode(!world.editor_mode);
+            }
+
+            // Save what's on screen to a timestamped file in the working directory
+            if input_map.pressed(&input, Action::Screenshot) {
+                let path = format!("screenshot-{}.png", get_current_time());
+                let result = world.capture_frame(pixels.get_frame())
+                    .and_then(|image| Ok(image.save(&path)?));
+                match result {
+                    Ok(()) => info!("saved screenshot to {path}"),
+                    Err(err) => error!("failed to save screenshot {path}: {err}"),
+                }
+            }
+
+            // Preview every animation of the player
+            if cfg!(debug_assertions) {
+                if input_map.pressed(&input, Action::NextAnimation) {
+                    world.sprites[0].next_animation();
+                } else if input_map.pressed(&input, Action::PrevAnimation) {
+                    world.sprites[0].prev_animation();
+                }
+            }
+
+            // Forward movement as the arrow key it's bound to
+            for (action, key) in [
+                (Action::Right, VirtualKeyCode::Right),
+                (Action::Left, VirtualKeyCode::Left),
+                (Action::Up, VirtualKeyCode::Up),
+                (Action::Down, VirtualKeyCode::Down),
+            ] {
+                if input_map.pressed(&input, action) {
+                    world.key_held(key);
+                } else if input_map.released(&input, action) {
+                    world.key_released(key);
+                }
+            }
+
+            // Zoom the editor camera with the mouse wheel
+            let scroll = input.scroll_diff();
+            if scroll != 0.0 {
+                world.zoom_editor(scroll);
+            }
+
+            // Track the mouse in world pixels
+            let render_scale = world.render_scale.max(1) as f32;
+            let mouse = input.mouse()
+                .and_then(|position| pixels.window_pos_to_pixel(position).ok())
+                .map(|(x, y)| (x as f32 / render_scale, y as f32 / render_scale));
+            world.set_mouse_position(mouse);
+
+            // Resize the surface to the window's new physical size, including when it moves to a display
+            // with a different scale factor
+            let resized = input.window_resized()
+                .or_else(|| input.scale_factor_changed().map(|scale_factor| physical_size(window_logical_size(window_scale), scale_factor)));
+            if let Some(size) = resized {
+                if let Err(err) = pixels.resize_surface(size.width, size.height) {
+                    error!("pixels.resize_surface() failed: {err}");
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+            }
+
+            // Update internal state and request a redraw
+            let now = Instant::now();
+            world.step((now - last_update).as_secs_f64());
+            last_update = now;
+
+            for event in world.poll_events() {
+                debug!("{event:?}");
+            }
+            window.request_redraw();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sprite with a single frame filled with one color
+    pub(crate) fn solid_sprite(size:(u16, u16), color:[u8; 4]) -> Sprite {
+        let texture = DynamicImage::ImageRgba8(RgbaImage::from_pixel(size.0 as u32, size.1 as u32, Rgba(color)));
+        Sprite::new(SpriteSheet::new(texture, vec![Animation::new((0, 0), 1, 0)], size))
+    }
+
+    /// A world over a plain black background that doesn't load anything from disk
+    pub(crate) fn test_world(sprites:Vec<Sprite>) -> World {
+        let background = RgbaImage::from_pixel(WORLD_WIDTH, WORLD_HEIGHT, Rgba([0, 0, 0, 255]));
+        World::with_background(sprites, DynamicImage::ImageRgba8(background), PathBuf::new())
+    }
+
+    /// Draw the world into a new frame buffer of the right size
+    fn draw_frame(world:&mut World) -> Vec<u8> {
+        let (width, height) = world.render_size();
+        let mut frame = vec![0; (width * height * 4) as usize];
+        world.draw(&mut frame).unwrap();
+        frame
+    }
+
+    /// The color of an output pixel of a frame drawn by `world`
+    fn pixel(world:&World, frame:&[u8], x:u32, y:u32) -> [u8; 4] {
+        let index = ((y * world.render_size().0 + x) * 4) as usize;
+        frame[index..index + 4].try_into().unwrap()
+    }
+
+    /// A path in the temp directory that won't collide with other test runs
+    fn temp_path(name:&str) -> PathBuf {
+        std::env::temp_dir().join(format!("rustpixels-{}-{name}", std::process::id()))
+    }
+
+    /// A sprite with one looping animation of `num_frames` 8x8 frames side by side,
+    /// frame `i` filled with red `(i + 1) * 40`
+    fn strip_sprite(num_frames:u16, frame_duration:u64) -> Sprite {
+        let texture = RgbaImage::from_fn(num_frames as u32 * 8, 8, |x, _| Rgba([(x / 8 + 1) as u8 * 40, 0, 0, 255]));
+        let animations = vec![Animation::new((0, 0), num_frames, frame_duration)];
+        Sprite::new(SpriteSheet::new(DynamicImage::ImageRgba8(texture), animations, (8, 8)))
+    }
+
+    #[test]
+    fn debug_bounds_outline_sprites() {
+        let mut sprite = solid_sprite((8, 8), [255, 0, 0, 255]);
+        sprite.position = (20.0, 20.0);
+        let mut world = test_world(vec![sprite]);
+
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 20, 20), [255, 0, 0, 255]);
+
+        world.set_debug_bounds(true);
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 20, 20), DEBUG_BOUNDS_COLOR);
+        assert_eq!(pixel(&world, &frame, 27, 27), DEBUG_BOUNDS_COLOR);
+        assert_eq!(pixel(&world, &frame, 23, 23), [255, 0, 0, 255]);
+
+        // A scaled sprite collides with others by its scaled bounds, which are what get outlined,
+        // while the floor and walls still stop its unscaled size, which gets its own outline
+        world.sprites[0].scale = (2.0, 2.0);
+        let frame = draw_frame(&mut world);
+        assert_eq!(world.screen_bounds(&world.sprites[0], (0, 0)), world.sprites[0].bounds());
+        assert_eq!(world.sprites[0].bounds(), Rect::new(20, 20, 16, 16));
+        assert_eq!(pixel(&world, &frame, 35, 35), DEBUG_BOUNDS_COLOR);
+        assert_eq!(pixel(&world, &frame, 27, 27), DEBUG_BODY_COLOR);
+    }
+
+    #[test]
+    fn wrap_x_carries_sprites_across_the_right_edge() {
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (254.0, (WORLD_HEIGHT - 8) as f32);
+        player.velocity.0 = 4.0;
+        let mut world = test_world(vec![player]);
+        world.wrap_x = true;
+        world.right_held = true;
+
+        for _ in 0..3 {
+            world.physics_step(1.0 / 60.0);
+        }
+        let player = &world.sprites[0];
+        assert!(player.position.0 < 8.0, "player at {:?} didn't wrap", player.position);
+        assert!(player.velocity.0 >= 4.0, "wrapping changed the velocity to {}", player.velocity.0);
+
+        // Straddling the seam draws the sprite on both sides of the screen
+        world.sprites[0].position.0 = 252.0;
+        let frame = draw_frame(&mut world);
+        let y = WORLD_HEIGHT - 4;
+        assert_eq!(pixel(&world, &frame, 254, y), [255, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 2, y), [255, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 5, y), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn vec_render_target_round_trips_the_frame() {
+        let mut sprite = solid_sprite((4, 4), [0, 255, 0, 255]);
+        sprite.position = (10.0, 10.0);
+        let mut world = test_world(vec![sprite]);
+
+        let (width, height) = world.render_size();
+        let mut target = vec![0; (width * height * 4) as usize];
+        world.render(&mut target).unwrap();
+
+        assert_eq!(target, draw_frame(&mut world));
+        assert_eq!(pixel(&world, &target, 11, 11), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn draw_tiled_repeats_the_texture() {
+        let world = test_world(Vec::new());
+        let texture = DynamicImage::ImageRgba8(RgbaImage::from_fn(3, 3, |x, y| Rgba([x as u8 * 100, y as u8 * 100, 0, 255])));
+        let (width, height) = world.render_size();
+        let mut frame = vec![0; (width * height * 4) as usize];
+
+        world.draw_tiled(&mut frame, &texture, 5, 7, 10, 4);
+        assert_eq!(pixel(&world, &frame, 5, 7), [0, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 9, 8), [100, 100, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 14, 10), [0, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 12, 9), [100, 200, 0, 255]);
+
+        // Nothing is drawn outside the rectangle
+        assert_eq!(pixel(&world, &frame, 15, 7), [0, 0, 0, 0]);
+        assert_eq!(pixel(&world, &frame, 5, 11), [0, 0, 0, 0]);
+
+        // Tiles hanging off the world are clipped instead of panicking
+        world.draw_tiled(&mut frame, &texture, WORLD_WIDTH as i32 - 2, -2, 10, 4);
+        assert_eq!(pixel(&world, &frame, WORLD_WIDTH - 1, 0), [100, 200, 0, 255]);
+    }
+
+    #[test]
+    fn input_smoothing_ramps_velocity() {
+        let mut world = test_world(vec![solid_sprite((8, 8), [255, 0, 0, 255])]);
+        world.right_held = true;
+
+        // Without smoothing every held step adds the same fixed amount
+        world.update_movement();
+        assert_eq!(world.sprites[0].velocity.0, 0.3);
+        world.update_movement();
+        assert_eq!(world.sprites[0].velocity.0, 0.6);
+
+        world.sprites[0].velocity.0 = 0.0;
+        world.input_smoothing = 0.5;
+        let mut velocities = Vec::new();
+        for _ in 0..4 {
+            world.update_movement();
+            velocities.push(world.sprites[0].velocity.0);
+        }
+
+        // Speed keeps rising towards the run speed, by less each step
+        assert!(velocities[0] > 0.0 && velocities[3] < MAX_RUN_SPEED, "{velocities:?}");
+        for pair in velocities.windows(3) {
+            assert!(pair[2] - pair[1] < pair[1] - pair[0], "{velocities:?}");
+        }
+
+        // Letting go slows down over a few steps too
+        world.right_held = false;
+        world.update_movement();
+        assert!(world.sprites[0].velocity.0 > 0.0 && world.sprites[0].velocity.0 < velocities[3]);
+    }
+
+    #[test]
+    fn ui_sprites_ignore_the_camera() {
+        let mut sprite = solid_sprite((4, 4), [0, 255, 0, 255]);
+        sprite.position = (50.0, 50.0);
+        let mut world = test_world(vec![sprite]);
+        let mut hud = solid_sprite((4, 4), [0, 0, 255, 255]);
+        hud.position = (10.0, 10.0);
+        assert_eq!(world.add_ui_sprite(hud), 0);
+
+        world.set_camera(20.0, 0.0);
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 31, 51), [0, 255, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 51, 51), [0, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 11, 11), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn physics_runs_at_its_own_rate() {
+        for render_hz in [60, 144] {
+            let mut world = test_world(vec![solid_sprite((8, 8), [255, 0, 0, 255])]);
+            world.physics_hz = 30;
+
+            let steps: u32 = (0..render_hz).map(|_| world.step(1.0 / render_hz as f64)).sum();
+            assert!((29..=30).contains(&steps), "{steps} physics steps at {render_hz} fps");
+            assert_eq!(world.frame_count, steps as u64);
+        }
+    }
+
+    #[test]
+    fn from_row_uses_grid_coordinates() {
+        let animation = Animation::from_row(2, 0, 4, (50, 37), 100);
+        assert_eq!(animation.starting_frame_position, (0, 74));
+        assert_eq!(animation.current_position, (0, 74));
+        assert_eq!((animation.num_frames, animation.frame_duration), (4, 100));
+
+        assert_eq!(Animation::from_row(1, 3, 2, (50, 37), 100).starting_frame_position, (150, 37));
+    }
+
+    #[test]
+    fn lock_aspect_keeps_scaling_uniform() {
+        let mut sprite = solid_sprite((4, 4), [255, 0, 0, 255]);
+        sprite.position = (20.0, 20.0);
+        sprite.set_scale(2.0, 1.0);
+        assert_eq!(sprite.scale, (2.0, 1.0));
+
+        sprite.lock_aspect = true;
+        sprite.set_scale(2.0, 1.0);
+        assert_eq!(sprite.scale, (2.0, 2.0));
+        assert_eq!(sprite.scaled_size(), (8, 8));
+
+        sprite.uniform_scale(3.0);
+        assert_eq!(sprite.scale, (3.0, 3.0));
+        sprite.uniform_scale(2.0);
+
+        // The square sprite is still drawn square
+        let mut world = test_world(vec![sprite]);
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 27, 27), [255, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 28, 27), [0, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 27, 28), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn restart_animation_rewinds_to_the_first_frame() {
+        let mut sprite = strip_sprite(4, 100);
+        sprite.advance_frames(2);
+        assert_eq!(sprite.animation_frame(), 2);
+        assert_eq!(sprite.get_sheet_offset(), (16, 0));
+
+        sprite.restart_animation();
+        assert_eq!(sprite.animation_frame(), 0);
+        assert_eq!(sprite.get_sheet_offset(), (0, 0));
+        assert_eq!(sprite.sprite_sheet.animations[0].previous_frame_time, 0);
+    }
+
+    #[test]
+    fn far_away_sprites_draw_nothing() {
+        let mut empty_world = test_world(Vec::new());
+        let background = draw_frame(&mut empty_world);
+
+        for position in [(-1.0e9, -1.0e9), (-40000.0, 20.0), (1.0e9, 1.0e9), (f32::MIN, f32::MAX)] {
+            let mut sprite = solid_sprite((8, 8), [255, 0, 0, 255]);
+            sprite.position = position;
+            sprite.outline = Some([255, 255, 255, 255]);
+            let mut world = test_world(vec![sprite]);
+
+            assert!(draw_frame(&mut world) == background, "sprite at {position:?} changed the frame");
+        }
+    }
+
+    #[test]
+    fn substeps_stop_fast_sprites_on_thin_ground() {
+        // A single row of solid tiles one sprite height thick, well above the world floor
+        let columns = (WORLD_WIDTH / 8) as usize;
+        let mut tiles = vec![TileShape::Empty; columns * 8];
+        tiles.extend(vec![TileShape::Solid; columns]);
+        let map = CollisionMap { tile_size: 8, width: columns, tiles };
+
+        for (substeps, lands) in [(1, false), (8, true)] {
+            let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+            player.position = (20.0, 40.0);
+            player.velocity.1 = 200.0;
+            let mut world = test_world(vec![player]);
+            world.physics.swept_floor = false;
+            world.collision_map = Some(map.clone());
+            world.physics_substeps = substeps;
+
+            world.physics_step(1.0 / 60.0);
+            let player = &world.sprites[0];
+            if lands {
+                assert_eq!(player.position.1, 56.0);
+                assert_eq!(player.velocity.1, 0.0);
+            } else {
+                assert!(player.position.1 > 64.0, "player stopped at {} without substeps", player.position.1);
+            }
+        }
+    }
+
+    #[test]
+    fn mirror_background_reverses_columns() {
+        let mut background = RgbaImage::from_pixel(WORLD_WIDTH, WORLD_HEIGHT, Rgba([0, 0, 0, 255]));
+        background.put_pixel(0, 5, Rgba([255, 0, 0, 255]));
+        background.put_pixel(WORLD_WIDTH - 1, 5, Rgba([0, 0, 255, 255]));
+        let mut world = World::with_background(Vec::new(), DynamicImage::ImageRgba8(background), PathBuf::new());
+
+        world.mirror_background();
+        assert_eq!(world.sample_background(0, 5), [0, 0, 255, 255]);
+        assert_eq!(world.sample_background(WORLD_WIDTH as i32 - 1, 5), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn saved_state_loads_into_a_fresh_world() {
+        let sprites = || vec![solid_sprite((8, 8), [255, 0, 0, 255]), strip_sprite(4, 100)];
+        let mut world = test_world(sprites());
+        world.sprites[0].position = (30.0, 10.0);
+        world.sprites[1].velocity = (5.0, -2.0);
+        world.sprites[1].advance_frames(2);
+        world.set_camera(12.0, 3.0);
+        for _ in 0..5 {
+            world.physics_step(1.0 / 60.0);
+        }
+
+        let path = temp_path("state.ron");
+        world.save_state(&path).unwrap();
+        let mut loaded = test_world(sprites());
+        loaded.load_state(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.snapshot(), world.snapshot());
+        assert_eq!(loaded.sprites[1].position, world.sprites[1].position);
+        assert_eq!(loaded.sprites[0].velocity, world.sprites[0].velocity);
+        assert_eq!(loaded.sprites[1].animation_frame(), 2);
+        assert_eq!(loaded.camera, (12.0, 3.0));
+
+        // A state can't be loaded into a world with different sprites
+        assert!(test_world(Vec::new()).restore(&world.snapshot()).is_err());
+    }
+
+    #[test]
+    fn background_tint_leaves_sprites_alone() {
+        let background = RgbaImage::from_pixel(WORLD_WIDTH, WORLD_HEIGHT, Rgba([200, 100, 50, 255]));
+        let mut sprite = solid_sprite((4, 4), [200, 100, 50, 255]);
+        sprite.position = (10.0, 10.0);
+        let mut world = World::with_background(vec![sprite], DynamicImage::ImageRgba8(background), PathBuf::new());
+
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 0, 0), [200, 100, 50, 255]);
+
+        world.background_tint = [0.5, 0.5, 0.5];
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 0, 0), [100, 50, 25, 255]);
+        assert_eq!(pixel(&world, &frame, 11, 11), [200, 100, 50, 255]);
+    }
+
+    #[test]
+    fn children_follow_their_parent() {
+        let parent = solid_sprite((16, 8), [255, 0, 0, 255]);
+        let mut child = solid_sprite((4, 4), [0, 255, 0, 255]);
+        child.attach_to(0, (12.0, 2.0));
+        let mut world = test_world(vec![parent, child]);
+
+        world.sprites[0].position = (100.0, 50.0);
+        world.update_attachments();
+        assert_eq!(world.sprites[1].position, (112.0, 52.0));
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 113, 53), [0, 255, 0, 255]);
+
+        // Turning around mirrors the offset to the other side of the parent
+        world.sprites[0].facing_left = true;
+        world.update_attachments();
+        assert_eq!(world.sprites[1].position, (100.0, 52.0));
+        assert!(world.sprites[1].facing_left);
+
+        world.sprites[1].detach();
+        world.sprites[0].position = (0.0, 0.0);
+        world.update_attachments();
+        assert_eq!(world.sprites[1].position, (100.0, 52.0));
+    }
+
+    #[test]
+    fn sprites_iter_mut_reaches_every_sprite() {
+        let sprites = (0..5).map(|i| {
+            let mut sprite = solid_sprite((4, 4), [255, 0, 0, 255]);
+            sprite.position = (i as f32 * 10.0, 20.0);
+            sprite
+        }).collect();
+        let mut world = test_world(sprites);
+
+        for sprite in world.sprites_iter_mut() {
+            sprite.position.1 += 5.0;
+        }
+
+        assert_eq!(world.sprites_iter().count(), 5);
+        for (i, sprite) in world.sprites_iter().enumerate() {
+            assert_eq!(sprite.position, (i as f32 * 10.0, 25.0));
+        }
+    }
+
+    #[test]
+    fn long_frames_count_as_dropped() {
+        let mut world = test_world(Vec::new());
+        world.target_frame_time = 1.0 / 60.0;
+
+        assert!(!world.record_frame_time(1.0 / 60.0));
+        assert!(!world.record_frame_time(1.4 / 60.0));
+        assert_eq!(world.dropped_frames(), 0);
+
+        assert!(world.record_frame_time(0.1));
+        assert!(world.record_frame_time(2.0 / 60.0));
+        assert_eq!(world.dropped_frames(), 2);
+    }
+
+    #[test]
+    fn frames_draw_at_their_own_size() {
+        let texture = DynamicImage::ImageRgba8(RgbaImage::from_pixel(24, 16, Rgba([255, 0, 0, 255])));
+        let frames = vec![
+            Frame { position: (0, 0), size: (8, 8), duration: None },
+            Frame { position: (8, 0), size: (16, 16), duration: None },
+        ];
+        let mut sprite = Sprite::new(SpriteSheet::new(texture, vec![Animation::from_frames(frames, 0)], (8, 8)));
+        sprite.position = (10.0, 10.0);
+        let mut world = test_world(vec![sprite]);
+
+        let red_pixels = |frame:&[u8]| frame.chunks_exact(4).filter(|pixel| *pixel == [255, 0, 0, 255]).count();
+        assert_eq!(red_pixels(&draw_frame(&mut world)), 8 * 8);
+
+        world.sprites[0].advance_frames(1);
+        assert_eq!(world.sprites[0].frame_size(), (16, 16));
+        assert_eq!(red_pixels(&draw_frame(&mut world)), 16 * 16);
+    }
+
+    #[test]
+    fn coordinate_system_flips_the_y_axis() {
+        let mut sprite = solid_sprite((4, 4), [255, 0, 0, 255]);
+        sprite.position = (10.0, 0.0);
+        let mut world = test_world(vec![sprite]);
+
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 11, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 11, WORLD_HEIGHT - 1), [0, 0, 0, 255]);
+        assert!(!world.on_floor(&world.sprites[0]));
+
+        world.coordinate_system = CoordinateSystem::BottomLeftYUp;
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 11, 0), [0, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 11, WORLD_HEIGHT - 1), [255, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 11, WORLD_HEIGHT - 4), [255, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 11, WORLD_HEIGHT - 5), [0, 0, 0, 255]);
+        assert!(world.on_floor(&world.sprites[0]));
+    }
+
+    #[test]
+    fn hot_reload_picks_up_changed_textures() {
+        let path = temp_path("hot-reload.png");
+        RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 255])).save(&path).unwrap();
+        let mut sprite = Sprite::new(SpriteSheet::load(&path, vec![Animation::new((0, 0), 1, 0)], (8, 8)).unwrap());
+        sprite.position = (10.0, 10.0);
+        let mut world = test_world(vec![sprite]);
+        world.enable_hot_reload(true);
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 12, 12), [255, 0, 0, 255]);
+
+        // Rewrite the file and move its modification time on, as an editor saving it would
+        RgbaImage::from_pixel(8, 8, Rgba([0, 0, 255, 255])).save(&path).unwrap();
+        let later = SystemTime::now() + Duration::from_secs(60);
+        fs::File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+
+        world.last_reload_check = 0;
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 12, 12), [0, 0, 255, 255]);
+        assert_eq!(world.reload_changed_assets(), 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn effects_despawn_once_their_animation_finishes() {
+        let mut world = test_world(vec![solid_sprite((8, 8), [255, 0, 0, 255])]);
+        world.clock_mode = ClockMode::Ticks;
+        let index = world.spawn_effect(strip_sprite(3, 1), 0).unwrap();
+        assert_eq!(index, 1);
+
+        // Drawing plays the animation, but only the update step removes the finished effect
+        for _ in 0..5 {
+            draw_frame(&mut world);
+        }
+        assert!(world.sprites[1].animation_finished());
+        assert_eq!(world.sprites.len(), 2);
+
+        world.physics_step(1.0 / 60.0);
+        assert_eq!(world.sprites.len(), 1);
+    }
+
+    #[test]
+    fn column_major_animations_step_down_first() {
+        let mut animation = Animation::new((0, 0), 6, 100);
+        animation.layout = AnimationLayout::ColumnMajor;
+        let texture = DynamicImage::ImageRgba8(RgbaImage::new(16, 24));
+        let mut sprite = Sprite::new(SpriteSheet::new(texture, vec![animation], (8, 8)));
+
+        let mut positions = vec![sprite.get_sheet_offset()];
+        for _ in 0..6 {
+            sprite.advance_frames(1);
+            positions.push(sprite.get_sheet_offset());
+        }
+        assert_eq!(positions, [(0, 0), (0, 8), (0, 16), (8, 0), (8, 8), (8, 16), (0, 0)]);
+    }
+
+    #[test]
+    fn behaviors_run_every_step() {
+        let mut walker = solid_sprite((4, 4), [0, 255, 0, 255]);
+        walker.position = (0.0, 20.0);
+        walker.set_behavior(|sprite, context| {
+            assert_eq!(context.sprites.len(), 2);
+            sprite.position.0 += 1.0;
+        });
+        let mut world = test_world(vec![solid_sprite((8, 8), [255, 0, 0, 255]), walker]);
+
+        for _ in 0..10 {
+            world.physics_step(1.0 / 60.0);
+        }
+        assert_eq!(world.sprites[1].position, (10.0, 20.0));
+    }
+
+    #[test]
+    fn drawing_stays_inside_the_clip() {
+        let mut sprite = solid_sprite((8, 8), [255, 0, 0, 255]);
+        sprite.position = (16.0, 16.0);
+        let mut world = test_world(vec![sprite]);
+        let clip = Rect::new(0, 0, 20, 20);
+        world.push_clip(clip);
+
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 17, 17), [255, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 19, 19), [255, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 5, 5), [0, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 20, 17), [0, 0, 0, 0]);
+        assert_eq!(pixel(&world, &frame, 21, 21), [0, 0, 0, 0]);
+
+        let mut frame = frame;
+        world.draw_rect(&mut frame, Rect::new(18, 18, 10, 10), [0, 255, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 18, 18), [0, 255, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 27, 18), [0, 0, 0, 0]);
+
+        assert_eq!(world.pop_clip(), Some(clip));
+        assert_eq!(world.pop_clip(), None);
+    }
+
+    #[test]
+    fn gif_frames_become_an_animation() {
+        let path = temp_path("animation.gif");
+        let gif_frame = |color:[u8; 4], delay_ms:u32| image::Frame::from_parts(
+            RgbaImage::from_pixel(4, 6, Rgba(color)),
+            0,
+            0,
+            image::Delay::from_numer_denom_ms(delay_ms, 1),
+        );
+        let mut encoder = image::codecs::gif::GifEncoder::new(fs::File::create(&path).unwrap());
+        encoder.encode_frames(vec![
+            gif_frame([255, 0, 0, 255], 100),
+            gif_frame([0, 255, 0, 255], 50),
+            gif_frame([0, 0, 255, 255], 0),
+        ]).unwrap();
+        drop(encoder);
+
+        let mut sheet = SpriteSheet::from_gif(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(sheet.frame_size, (4, 6));
+        assert_eq!(sheet.texture.dimensions(), (12, 6));
+
+        let animation = &mut sheet.animations[0];
+        assert_eq!(animation.num_frames, 3);
+        let mut durations = Vec::new();
+        for _ in 0..3 {
+            durations.push(animation.current_frame_duration());
+            animation.advance_frames(1, (4, 6), (0, 0), (12, 6));
+        }
+        assert_eq!(durations, [100, 50, DEFAULT_FRAME_DURATION]);
+    }
+
+    #[test]
+    fn draw_rejects_wrongly_sized_buffers() {
+        let mut world = test_world(vec![solid_sprite((8, 8), [255, 0, 0, 255])]);
+        let (width, height) = world.render_size();
+
+        for len in [0, 4, (width * height * 4 - 4) as usize, (width * height * 4 + 4) as usize] {
+            let mut frame = vec![7; len];
+            let err = world.draw(&mut frame).unwrap_err();
+            assert!(err.to_string().contains(&format!("frame buffer is {len} bytes")), "{err}");
+            assert!(frame.iter().all(|&value| value == 7));
+        }
+    }
+
+    #[test]
+    fn premultiplied_sheets_are_not_darkened() {
+        let mut straight = solid_sprite((4, 4), [200, 0, 0, 128]);
+        straight.position = (10.0, 10.0);
+        let mut premultiplied = solid_sprite((4, 4), [100, 0, 0, 128]);
+        premultiplied.position = (30.0, 10.0);
+        premultiplied.sprite_sheet.premultiplied = true;
+        let mut misread = solid_sprite((4, 4), [100, 0, 0, 128]);
+        misread.position = (50.0, 10.0);
+
+        let background = RgbaImage::from_pixel(WORLD_WIDTH, WORLD_HEIGHT, Rgba([200, 200, 200, 255]));
+        let sprites = vec![straight, premultiplied, misread];
+        let mut world = World::with_background(sprites, DynamicImage::ImageRgba8(background), PathBuf::new());
+
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 11, 11), [199, 99, 99, 255]);
+        assert_eq!(pixel(&world, &frame, 31, 11), [199, 99, 99, 255]);
+
+        // Blending premultiplied colors as straight alpha applies the alpha twice
+        assert_eq!(pixel(&world, &frame, 51, 11), [149, 99, 99, 255]);
+    }
+
+    #[test]
+    fn icy_ground_slides_farther() {
+        let distance = |ground_friction:f32| {
+            let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+            player.position = (20.0, (WORLD_HEIGHT - 8) as f32);
+            player.velocity.0 = 4.0;
+            let mut world = test_world(vec![player]);
+            world.physics.ground_friction = ground_friction;
+
+            for _ in 0..60 {
+                world.physics_step(1.0 / 60.0);
+            }
+            world.sprites[0].position.0 - 20.0
+        };
+
+        let normal = distance(PhysicsConfig::default().ground_friction);
+        let icy = distance(0.01);
+        assert!(icy > normal * 2.0, "icy slide of {icy} vs {normal}");
+    }
+
+    #[test]
+    fn cursor_sprite_follows_the_mouse() {
+        let mut world = test_world(Vec::new());
+        world.set_cursor_sprite(solid_sprite((3, 3), [255, 255, 0, 255]));
+        world.set_camera(30.0, 0.0);
+
+        // Nothing is drawn while the mouse is outside the window
+        let frame = draw_frame(&mut world);
+        assert!(frame.chunks_exact(4).all(|pixel| pixel == [0, 0, 0, 255]));
+
+        world.set_mouse_position(Some((40.0, 20.0)));
+        let frame = draw_frame(&mut world);
+        assert_eq!(world.mouse_world_position(), Some((70.0, 20.0)));
+        assert_eq!(world.cursor_sprite.as_ref().unwrap().position, (70.0, 20.0));
+        assert_eq!(pixel(&world, &frame, 40, 20), [255, 255, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 42, 22), [255, 255, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 43, 20), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn replaying_a_recording_reproduces_it() {
+        let player = || {
+            let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+            player.position = (20.0, (WORLD_HEIGHT - 8) as f32);
+            player
+        };
+        let path = temp_path("recording.ron");
+        let _ = fs::remove_file(&path);
+
+        let mut world = test_world(vec![player()]);
+        world.start_recording(&path).unwrap();
+        for frame in 0..40 {
+            world.apply_input(InputState { left: false, right: frame < 30, jump: frame == 5 });
+            world.physics_step(1.0 / 60.0);
+        }
+        world.stop_recording().unwrap();
+
+        let recorded: Vec<FrameRecord> = fs::read_to_string(&path).unwrap()
+            .lines()
+            .map(|line| ron::from_str(line).unwrap())
+            .collect();
+        assert_eq!(recorded.len(), 40);
+        assert!(recorded.iter().any(|record| record.input.jump));
+
+        let mut replay_world = test_world(vec![player()]);
+        let replayed = replay_world.replay(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(replayed, recorded);
+        assert_eq!(replay_world.sprites[0].position, world.sprites[0].position);
+    }
+
+    #[test]
+    fn aseprite_tags_become_animations() {
+        let png_path = temp_path("aseprite.png");
+        let json_path = temp_path("aseprite.json");
+        RgbaImage::new(32, 8).save(&png_path).unwrap();
+        fs::write(&json_path, r#"{
+            "frames": [
+                { "filename": "hero 0.aseprite", "frame": { "x": 0, "y": 0, "w": 8, "h": 8 }, "duration": 100 },
+                { "filename": "hero 1.aseprite", "frame": { "x": 8, "y": 0, "w": 8, "h": 8 }, "duration": 150 },
+                { "filename": "hero 2.aseprite", "frame": { "x": 16, "y": 0, "w": 8, "h": 8 }, "duration": 200 },
+                { "filename": "hero 3.aseprite", "frame": { "x": 24, "y": 0, "w": 8, "h": 8 }, "duration": 250 }
+            ],
+            "meta": {
+                "image": "aseprite.png",
+                "frameTags": [
+                    { "name": "idle", "from": 0, "to": 1, "direction": "forward" },
+                    { "name": "attack", "from": 1, "to": 3, "direction": "pingpong" }
+                ]
+            }
+        }"#).unwrap();
+
+        let sheet = SpriteSheet::from_aseprite(&png_path, &json_path).unwrap();
+        fs::remove_file(&png_path).unwrap();
+        fs::remove_file(&json_path).unwrap();
+
+        let summary: Vec<_> = sheet.animations.iter().map(|animation| (
+            animation.name.as_str(),
+            animation.num_frames,
+            animation.frames.iter().map(|frame| frame.position).collect(),
+            animation.frames.iter().map(|frame| frame.duration).collect(),
+        )).collect();
+        assert_eq!(summary, [
+            ("idle", 2, vec![(0, 0), (8, 0)], vec![Some(100), Some(150)]),
+            ("attack", 4, vec![(8, 0), (16, 0), (24, 0), (16, 0)], vec![Some(150), Some(200), Some(250), Some(200)]),
+        ]);
+        assert_eq!(sheet.frame_size, (8, 8));
+    }
+
+    #[test]
+    fn time_scale_slows_physics_and_animation() {
+        let distance = |time_scale:f32| {
+            let mut mover = solid_sprite((4, 4), [0, 255, 0, 255]);
+            mover.velocity = (5.0, 0.0);
+            let mut world = test_world(vec![solid_sprite((8, 8), [255, 0, 0, 255]), mover]);
+            world.time_scale = time_scale;
+            world.clock_mode = ClockMode::Ticks;
+
+            for _ in 0..60 {
+                world.step(1.0 / 60.0);
+                world.tick_animation_clock();
+            }
+            (world.sprites[1].position.0, world.animation_clock)
+        };
+
+        let (full_distance, full_clock) = distance(1.0);
+        let (slow_distance, slow_clock) = distance(0.5);
+        assert!((full_distance - 60.0).abs() <= 1.0, "moved {full_distance}");
+        assert!((slow_distance - full_distance / 2.0).abs() <= 1.0, "moved {slow_distance} in slow motion");
+        assert_eq!(slow_clock, full_clock / 2.0);
+        assert_eq!(distance(0.0), (0.0, 0.0));
+    }
+
+    #[test]
+    fn auto_face_follows_movement() {
+        let mut mover = solid_sprite((4, 4), [0, 255, 0, 255]);
+        mover.position = (100.0, 20.0);
+        mover.auto_face = true;
+        mover.velocity.0 = -2.0;
+        let mut world = test_world(vec![solid_sprite((8, 8), [255, 0, 0, 255]), mover]);
+
+        world.physics_step(1.0 / 60.0);
+        assert!(world.sprites[1].facing_left);
+
+        // Stopping keeps facing the way it last moved
+        world.sprites[1].velocity.0 = 0.0;
+        world.physics_step(1.0 / 60.0);
+        assert!(world.sprites[1].facing_left);
+
+        world.sprites[1].velocity.0 = 1.0;
+        world.physics_step(1.0 / 60.0);
+        assert!(!world.sprites[1].facing_left);
+    }
+
+    #[test]
+    fn draw_into_fills_only_its_region() {
+        let mut sprite = solid_sprite((8, 8), [255, 0, 0, 255]);
+        sprite.position = (20.0, 20.0);
+        let mut world = test_world(vec![sprite]);
+        let world_frame = draw_frame(&mut world);
+
+        let (width, height) = world.render_size();
+        let mut frame = vec![0; (width * 2 * height * 4) as usize];
+        world.draw_into(&mut frame, width * 2, Rect::new(width as i32, 0, width, height)).unwrap();
+
+        for (row, world_row) in frame.chunks_exact((width * 8) as usize).zip(world_frame.chunks_exact((width * 4) as usize)) {
+            let (left, right) = row.split_at((width * 4) as usize);
+            assert!(left.iter().all(|&value| value == 0));
+            assert_eq!(right, world_row);
+        }
+
+        assert!(world.draw_into(&mut frame, 7, Rect::new(0, 0, 1, 1)).is_err());
+    }
+
+    #[test]
+    fn editor_mode_pans_the_camera_instead_of_the_player() {
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (50.0, (WORLD_HEIGHT - 8) as f32);
+        let mut world = test_world(vec![player]);
+        world.set_editor_mode(true);
+
+        world.key_held(VirtualKeyCode::Right);
+        world.key_held(VirtualKeyCode::S);
+        for _ in 0..10 {
+            world.physics_step(1.0 / 60.0);
+        }
+        assert_eq!(world.camera, (10.0 * EDITOR_PAN_SPEED, 10.0 * EDITOR_PAN_SPEED));
+        assert_eq!(world.sprites[0].position, (50.0, (WORLD_HEIGHT - 8) as f32));
+        assert_eq!(world.sprites[0].velocity, (0.0, 0.0));
+
+        world.zoom_editor(1.0);
+        assert_eq!(world.camera_zoom, 1.25);
+
+        // Back out of the editor the same key moves the player again
+        world.set_editor_mode(false);
+        world.key_held(VirtualKeyCode::Right);
+        world.physics_step(1.0 / 60.0);
+        assert!(world.sprites[0].position.0 > 50.0);
+    }
+
+    #[test]
+    fn bgra_output_swaps_red_and_blue() {
+        let scene = || {
+            let mut sprite = solid_sprite((8, 8), [250, 120, 10, 255]);
+            sprite.position = (20.0, 20.0);
+            sprite.opacity = 0.5;
+            test_world(vec![sprite])
+        };
+        let mut rgba_world = scene();
+        let rgba = draw_frame(&mut rgba_world);
+        let mut bgra_world = scene();
+        bgra_world.pixel_format = PixelFormat::Bgra8;
+        let bgra = draw_frame(&mut bgra_world);
+
+        for (rgba, bgra) in rgba.chunks_exact(4).zip(bgra.chunks_exact(4)) {
+            assert_eq!([bgra[2], bgra[1], bgra[0], bgra[3]], rgba);
+        }
+        assert_eq!(bgra_world.pixel_at(&bgra, 21, 21), Some([124, 59, 4, 255]));
+        assert_eq!(pixel(&bgra_world, &bgra, 21, 21), [4, 59, 124, 255]);
+    }
+
+    #[test]
+    fn all_frames_animation_covers_the_grid() {
+        let texture = DynamicImage::ImageRgba8(RgbaImage::new(200, 74));
+        let sheet = SpriteSheet::new(texture, vec![Animation::new((0, 0), 1, 100)], (50, 37));
+        let animation = sheet.all_frames_animation(80);
+        assert_eq!((animation.num_frames, animation.frame_duration), (8, 80));
+
+        let positions: Vec<(u16, u16)> = animation
+            .frame_list(sheet.frame_size, sheet.frame_spacing, sheet.sheet_dimensions)
+            .iter()
+            .map(|frame| frame.position)
+            .collect();
+        assert_eq!(positions, [(0, 0), (50, 0), (100, 0), (150, 0), (0, 37), (50, 37), (100, 37), (150, 37)]);
+        assert_eq!(sheet.grid_position(3, 1), (150, 37));
+    }
+
+    #[test]
+    fn clamp_velocity_keeps_the_direction() {
+        let mut sprite = solid_sprite((4, 4), [255, 0, 0, 255]);
+        sprite.velocity = (6.0, -8.0);
+        sprite.clamp_velocity(5.0);
+        assert!((sprite.velocity.0 - 3.0).abs() < 1e-6 && (sprite.velocity.1 + 4.0).abs() < 1e-6, "{:?}", sprite.velocity);
+        assert!((sprite.velocity.0.hypot(sprite.velocity.1) - 5.0).abs() < 1e-6);
+
+        // Slower sprites are left alone
+        sprite.velocity = (1.0, 1.0);
+        sprite.clamp_velocity(5.0);
+        assert_eq!(sprite.velocity, (1.0, 1.0));
+
+        sprite.position = (-10.0, 300.0);
+        sprite.clamp_position((0.0, 0.0), (100.0, 50.0));
+        assert_eq!(sprite.position, (0.0, 50.0));
+    }
+
+    #[test]
+    fn fast_falls_land_exactly_on_the_floor() {
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (20.0, 10.0);
+        player.velocity.1 = 60.0;
+        let mut world = test_world(vec![player]);
+        let floor = (WORLD_HEIGHT - 8) as f32;
+
+        for _ in 0..20 {
+            world.physics_step(1.0 / 60.0);
+            assert!(world.sprites[0].position.1 <= floor, "player sank to {}", world.sprites[0].position.1);
+
+            let frame = draw_frame(&mut world);
+            assert_eq!(pixel(&world, &frame, 22, WORLD_HEIGHT - 1), if world.sprites[0].position.1 == floor {
+                [255, 0, 0, 255]
+            } else {
+                [0, 0, 0, 255]
+            });
+        }
+        assert_eq!(world.sprites[0].position.1, floor);
+        assert_eq!(world.sprites[0].velocity.1, 0.0);
+    }
+
+    #[test]
+    fn physics_and_animations_pause_independently() {
+        let mut mover = strip_sprite(4, 1);
+        mover.velocity = (5.0, 0.0);
+        let mut world = test_world(vec![solid_sprite((8, 8), [255, 0, 0, 255]), mover]);
+        world.clock_mode = ClockMode::Ticks;
+        let advance = |world:&mut World| {
+            let steps = world.step(1.0 / 60.0);
+            draw_frame(world);
+            steps
+        };
+
+        // Paused physics still animates
+        world.toggle_physics_pause();
+        assert_eq!(advance(&mut world), 0);
+        advance(&mut world);
+        assert_eq!(world.sprites[1].position.0, 0.0);
+        assert_eq!(world.sprites[1].animation_frame(), 2);
+
+        // Paused animations still simulate
+        world.toggle_physics_pause();
+        world.toggle_animations_pause();
+        assert_eq!(advance(&mut world), 1);
+        assert_eq!(world.sprites[1].position.0, 1.0);
+        assert_eq!(world.sprites[1].animation_frame(), 2);
+
+        // The global pause stops both, and resumes both
+        world.toggle_pause();
+        assert!(world.physics_paused && world.animations_paused);
+        assert_eq!(advance(&mut world), 0);
+        assert_eq!(world.sprites[1].animation_frame(), 2);
+        world.toggle_pause();
+        assert!(!world.physics_paused && !world.animations_paused);
+        assert_eq!(advance(&mut world), 1);
+        assert_eq!(world.sprites[1].animation_frame(), 3);
+    }
+
+    #[test]
+    fn frame_spacing_skips_gutters() {
+        // Three 8x8 frames a row with 2px magenta gutters between them, frame i of a row is red i * 100
+        let texture = RgbaImage::from_fn(28, 18, |x, y| match (x % 10, y % 10) {
+            (8.., _) | (_, 8..) => Rgba([255, 0, 255, 255]),
+            _ => Rgba([(x / 10) as u8 * 100, 0, 0, 255]),
+        });
+        let mut sheet = SpriteSheet::new(DynamicImage::ImageRgba8(texture), vec![Animation::new((0, 0), 6, 100)], (8, 8));
+        sheet.frame_spacing = (2, 2);
+        let mut sprite = Sprite::new(sheet);
+
+        let mut positions = vec![sprite.get_sheet_offset()];
+        for _ in 0..5 {
+            sprite.advance_frames(1);
+            positions.push(sprite.get_sheet_offset());
+        }
+        assert_eq!(positions, [(0, 0), (10, 0), (20, 0), (0, 10), (10, 10), (20, 10)]);
+
+        // The drawn frame holds none of the gutter
+        sprite.position = (10.0, 10.0);
+        let mut world = test_world(vec![sprite]);
+        world.clock_mode = ClockMode::Ticks;
+        let frame = draw_frame(&mut world);
+        for (x, y) in [(10, 10), (17, 10), (10, 17), (17, 17)] {
+            assert_eq!(pixel(&world, &frame, x, y), [200, 0, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn fade_out_is_half_done_at_its_midpoint() {
+        let background = RgbaImage::from_pixel(WORLD_WIDTH, WORLD_HEIGHT, Rgba([255, 255, 255, 255]));
+        let mut world = World::with_background(Vec::new(), DynamicImage::ImageRgba8(background), PathBuf::new());
+        world.clock_mode = ClockMode::Ticks;
+        world.time_scale = 100.0;
+
+        world.start_fade([0, 0, 0], 1000, FadeDirection::Out);
+        let fade = world.fade.unwrap();
+        assert!((fade.alpha(fade.start_time + 500.0) - 0.5).abs() < 1e-6);
+
+        // Each drawn frame is 100ms of animation time here
+        for _ in 0..4 {
+            draw_frame(&mut world);
+        }
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 100, 100), [127, 127, 127, 255]);
+
+        for _ in 0..10 {
+            draw_frame(&mut world);
+        }
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 100, 100), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn rotation_grows_the_rendered_aabb() {
+        let mut sprite = solid_sprite((10, 10), [255, 0, 0, 255]);
+        sprite.position = (50.0, 50.0);
+        assert_eq!(sprite.rendered_aabb(), (50.0, 50.0, 60.0, 60.0));
+
+        sprite.rotation = std::f32::consts::FRAC_PI_4;
+        let (min_x, min_y, max_x, max_y) = sprite.rendered_aabb();
+        let expected = 10.0 * std::f32::consts::SQRT_2;
+        assert!((max_x - min_x - expected).abs() < 1e-4 && (max_y - min_y - expected).abs() < 1e-4);
+
+        // Rotating around the center keeps the box centered on the sprite
+        assert!(((min_x + max_x) / 2.0 - 55.0).abs() < 1e-4 && ((min_y + max_y) / 2.0 - 55.0).abs() < 1e-4);
+
+        sprite.scale = (2.0, 1.0);
+        sprite.rotation = 0.0;
+        assert_eq!(sprite.rendered_aabb(), (50.0, 50.0, 70.0, 60.0));
+    }
+
+    #[test]
+    fn off_screen_sprites_are_culled() {
+        let mut empty_world = test_world(Vec::new());
+        let background = draw_frame(&mut empty_world);
+
+        let mut visible = solid_sprite((8, 8), [255, 0, 0, 255]);
+        visible.position = (20.0, 20.0);
+        let mut far_away = solid_sprite((8, 8), [0, 255, 0, 255]);
+        far_away.position = (1000.0, 20.0);
+        let mut world = test_world(vec![visible, far_away]);
+
+        let frame = draw_frame(&mut world);
+        assert_eq!(world.culled_sprites(), 1);
+        assert_eq!(frame.chunks_exact(4).filter(|pixel| *pixel == [0, 255, 0, 255]).count(), 0);
+
+        // Moving the camera over to it brings it back into view
+        world.set_camera(990.0, 0.0);
+        let frame = draw_frame(&mut world);
+        assert_eq!(world.culled_sprites(), 1);
+        assert_eq!(pixel(&world, &frame, 10, 20), [0, 255, 0, 255]);
+
+        world.sprites.truncate(1);
+        world.sprites[0].position = (-500.0, -500.0);
+        world.set_camera(0.0, 0.0);
+        assert!(draw_frame(&mut world) == background);
+        assert_eq!(world.culled_sprites(), 1);
+    }
+
+    #[test]
+    fn controls_file_overrides_default_bindings() {
+        let path = temp_path("controls.ron");
+        fs::write(&path, "(bindings: { Jump: [Space, W], Pause: [Escape] })").unwrap();
+        let map = InputMap::from_file(&path).unwrap();
+
+        assert_eq!(map.keys(Action::Jump), [VirtualKeyCode::Space, VirtualKeyCode::W]);
+        assert_eq!(map.keys(Action::Pause), [VirtualKeyCode::Escape]);
+        assert_eq!(map.keys(Action::Left), InputMap::default().keys(Action::Left));
+
+        fs::write(&path, "(bindings: { Jump: [NotAKey] })").unwrap();
+        assert!(InputMap::from_file(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(InputMap::from_file(&path).unwrap(), InputMap::default());
+    }
+
+    #[test]
+    fn contact_sheet_shows_every_frame() {
+        let mut sheet = strip_sprite(3, 100).sprite_sheet;
+        sheet.animations.push(Animation::new((8, 0), 2, 100));
+        let contact_sheet = sheet.render_contact_sheet().to_rgba8();
+
+        // Five 8x8 frames fit a 3x2 grid, the unused cell shows the checkerboard
+        assert_eq!(contact_sheet.dimensions(), (24, 16));
+        let cell_colors: Vec<[u8; 4]> = (0..6).map(|i| contact_sheet.get_pixel(i % 3 * 8 + 4, i / 3 * 8 + 4).0).collect();
+        assert_eq!(cell_colors, [
+            [40, 0, 0, 255],
+            [80, 0, 0, 255],
+            [120, 0, 0, 255],
+            [80, 0, 0, 255],
+            [120, 0, 0, 255],
+            CHECKER_COLORS[0],
+        ]);
+    }
+
+    #[test]
+    fn landing_emits_one_boundary_event() {
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (50.0, 100.0);
+        let mut world = test_world(vec![player]);
+
+        for _ in 0..120 {
+            world.physics_step(1.0 / 60.0);
+        }
+        let events = world.poll_events();
+        assert_eq!(events, [EngineEvent::HitBoundary { sprite: 0, side: Side::Bottom }]);
+        assert!(world.poll_events().is_empty());
+    }
+
+    #[test]
+    fn logical_window_size_doubles_at_2x() {
+        let logical = window_logical_size(WINDOW_SCALE);
+        assert_eq!((logical.width, logical.height), ((WORLD_WIDTH * WINDOW_SCALE) as f64, (WORLD_HEIGHT * WINDOW_SCALE) as f64));
+
+        assert_eq!(physical_size(logical, 2.0), PhysicalSize::new(WORLD_WIDTH * WINDOW_SCALE * 2, WORLD_HEIGHT * WINDOW_SCALE * 2));
+        assert_eq!(physical_size(window_logical_size(1), 1.5), PhysicalSize::new(384, 216));
+    }
+
+    #[test]
+    fn long_frames_catch_up_at_most_max_frame_skip_steps() {
+        let mut world = test_world(vec![solid_sprite((8, 8), [255, 0, 0, 255])]);
+        world.max_frame_skip = 5;
+
+        // A whole second behind only runs five steps and drops the rest instead of piling up
+        assert_eq!(world.step(1.0), 5);
+        assert_eq!(world.frame_count, 5);
+        assert!(world.physics_accumulator < 1.0 / 60.0);
+
+        // Three steps' worth of time fits under the cap and runs in full
+        world.physics_accumulator = 0.0;
+        assert_eq!(world.step(3.0 / 60.0 + 1e-9), 3);
+        assert_eq!(world.frame_count, 8);
+    }
+
+    #[test]
+    fn clip_alpha_mode_copies_or_skips_pixels() {
+        let texture = RgbaImage::from_fn(2, 1, |x, _| if x == 0 { Rgba([255, 0, 0, 50]) } else { Rgba([0, 255, 0, 200]) });
+        let mut sprite = Sprite::new(SpriteSheet::new(DynamicImage::ImageRgba8(texture), vec![Animation::new((0, 0), 1, 0)], (2, 1)));
+        sprite.position = (10.0, 10.0);
+        sprite.sprite_sheet.alpha_mode = AlphaMode::Clip(128);
+        let background = RgbaImage::from_pixel(WORLD_WIDTH, WORLD_HEIGHT, Rgba([100, 100, 100, 255]));
+        let mut world = World::with_background(vec![sprite], DynamicImage::ImageRgba8(background), PathBuf::new());
+
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 10, 10), [100, 100, 100, 255]);
+        assert_eq!(pixel(&world, &frame, 11, 10), [0, 255, 0, 255]);
+
+        // Blending mixes both pixels with the background instead
+        world.sprites[0].sprite_sheet.alpha_mode = AlphaMode::Blend;
+        let frame = draw_frame(&mut world);
+        assert_ne!(pixel(&world, &frame, 10, 10), [100, 100, 100, 255]);
+        assert_ne!(pixel(&world, &frame, 11, 10), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn horizontal_axis_combines_held_keys() {
+        let mut world = test_world(vec![solid_sprite((8, 8), [255, 0, 0, 255])]);
+        for (left, right, axis) in [(false, false, 0.0), (true, false, -1.0), (false, true, 1.0), (true, true, 0.0)] {
+            world.left_held = left;
+            world.right_held = right;
+            assert_eq!(world.horizontal_axis(), axis, "left {left}, right {right}");
+        }
+    }
+
+    #[test]
+    fn spawning_past_max_sprites_fails() {
+        let mut world = test_world(Vec::new());
+        world.max_sprites = Some(2);
+
+        assert_eq!(world.spawn(solid_sprite((4, 4), [255, 0, 0, 255])).unwrap(), 0);
+        assert_eq!(world.spawn(solid_sprite((4, 4), [255, 0, 0, 255])).unwrap(), 1);
+        let err = world.spawn(solid_sprite((4, 4), [255, 0, 0, 255])).unwrap_err();
+        assert_eq!(err, SpawnError::AtCapacity(2));
+        assert_eq!(world.sprites.len(), 2);
+
+        world.max_sprites = None;
+        assert_eq!(world.spawn(solid_sprite((4, 4), [255, 0, 0, 255])).unwrap(), 2);
+    }
+
+    #[test]
+    fn outline_rings_the_sprite() {
+        let mut sprite = solid_sprite((4, 4), [255, 0, 0, 255]);
+        sprite.position = (20.0, 20.0);
+        sprite.outline = Some([255, 255, 255, 255]);
+        let mut world = test_world(vec![sprite]);
+        let frame = draw_frame(&mut world);
+
+        let outlined: Vec<(u32, u32)> = (15..30)
+            .flat_map(|y| (15..30).map(move |x| (x, y)))
+            .filter(|&(x, y)| pixel(&world, &frame, x, y) == [255, 255, 255, 255])
+            .collect();
+        let mut expected = Vec::new();
+        for y in 19..=24 {
+            for x in 19..=24 {
+                let inside = (20..24).contains(&x) && (20..24).contains(&y);
+                let corner = (x == 19 || x == 24) && (y == 19 || y == 24);
+                if !inside && !corner {
+                    expected.push((x, y));
+                }
+            }
+        }
+        assert_eq!(outlined, expected);
+        assert_eq!(pixel(&world, &frame, 20, 20), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn manifest_loads_a_whole_world() {
+        let directory = temp_path("manifest");
+        fs::create_dir_all(directory.join("sprites")).unwrap();
+        RgbaImage::from_pixel(WORLD_WIDTH, WORLD_HEIGHT, Rgba([0, 0, 255, 255])).save(directory.join("bg.png")).unwrap();
+        RgbaImage::from_pixel(16, 8, Rgba([255, 0, 0, 255])).save(directory.join("sprites/hero.png")).unwrap();
+        fs::write(directory.join("world.ron"), r#"(background: "bg.png", sprites: ["sprites/hero.ron"])"#).unwrap();
+        fs::write(directory.join("sprites/hero.ron"), r#"(
+            texture: "hero.png",
+            frame_size: (8, 8),
+            position: (30.0, 40.0),
+            animations: [(name: "walk", row: 0, frames: 2, frame_duration: 100)],
+        )"#).unwrap();
+
+        let world = World::from_manifest(&directory.join("world.ron")).unwrap();
+        assert_eq!(world.sprites.len(), 1);
+        let hero = &world.sprites[0];
+        assert_eq!((hero.position, hero.size), ((30.0, 40.0), (8, 8)));
+        assert_eq!(hero.sprite_sheet.animations[0].name, "walk");
+        assert_eq!(hero.sprite_sheet.animations[0].num_frames, 2);
+        assert_eq!(world.background_image.get_pixel(0, 0), Rgba([0, 0, 255, 255]));
+
+        // A missing sheet is reported by its path
+        fs::remove_file(directory.join("sprites/hero.png")).unwrap();
+        let err = World::from_manifest(&directory.join("world.ron")).err().unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+        assert!(matches!(&err, LoadError::Image { path, .. } if path.ends_with("sprites/hero.png")), "{err}");
+    }
+
+    #[test]
+    fn force_fields_push_overlapping_sprites() {
+        let mut crate_sprite = solid_sprite((4, 4), [0, 255, 0, 255]);
+        crate_sprite.position = (10.0, 20.0);
+        let mut world = test_world(vec![solid_sprite((8, 8), [255, 0, 0, 255]), crate_sprite]);
+        world.force_fields.push(ForceField { rect: Rect::new(0, 0, 40, 40), force: (0.5, 0.0) });
+
+        let mut velocities = Vec::new();
+        for _ in 0..5 {
+            world.physics_step(1.0 / 60.0);
+            velocities.push(world.sprites[1].velocity.0);
+        }
+        assert_eq!(velocities, [0.5, 1.0, 1.5, 2.0, 2.5]);
+        assert!(world.sprites[1].position.0 > 10.0);
+
+        // Outside the field the sprite keeps the speed it was given
+        world.sprites[1].position = (100.0, 20.0);
+        world.physics_step(1.0 / 60.0);
+        assert_eq!(world.sprites[1].velocity.0, 2.5);
+    }
+
+    #[test]
+    fn tick_clock_plays_animations_frame_exactly() {
+        let mut world = test_world(vec![strip_sprite(4, 2)]);
+        world.clock_mode = ClockMode::Ticks;
+
+        // Two ticks per frame, wrapping back to the start after the fourth
+        let mut positions = Vec::new();
+        for _ in 0..9 {
+            world.update_sprite_animations();
+            positions.push(world.sprites[0].get_sheet_offset().0);
+        }
+        assert_eq!(positions, [0, 8, 8, 16, 16, 24, 24, 0, 0]);
+        assert_eq!(world.animation_clock, 9.0);
+
+        // Starting a fade reads the clock without ticking it
+        world.start_fade([0, 0, 0], 10, FadeDirection::Out);
+        assert_eq!(world.animation_clock, 9.0);
+        assert_eq!(world.fade.unwrap().start_time, 9.0);
+    }
+
+    #[test]
+    fn shadows_fall_on_the_floor_under_the_sprite() {
+        let mut sprite = solid_sprite((16, 8), [255, 0, 0, 255]);
+        sprite.position = (40.0, 50.0);
+        sprite.cast_shadow = true;
+        let background = RgbaImage::from_pixel(WORLD_WIDTH, WORLD_HEIGHT, Rgba([200, 200, 200, 255]));
+        let mut world = World::with_background(vec![sprite], DynamicImage::ImageRgba8(background), PathBuf::new());
+        let frame = draw_frame(&mut world);
+
+        // A flat half-ellipse as wide as the sprite, centered under it on the bottom edge
+        let shadowed = [124, 124, 124, 255];
+        assert_eq!(pixel(&world, &frame, 48, WORLD_HEIGHT - 1), shadowed);
+        assert_eq!(pixel(&world, &frame, 41, WORLD_HEIGHT - 1), shadowed);
+        assert_eq!(pixel(&world, &frame, 48, WORLD_HEIGHT - 2), shadowed);
+        assert_eq!(pixel(&world, &frame, 38, WORLD_HEIGHT - 1), [200, 200, 200, 255]);
+        assert_eq!(pixel(&world, &frame, 48, WORLD_HEIGHT - 4), [200, 200, 200, 255]);
+    }
+
+    #[test]
+    fn integer_scale_fits_the_world() {
+        let world = (WORLD_WIDTH, WORLD_HEIGHT);
+        assert_eq!(integer_scale((1792, 1008), world), 7);
+        assert_eq!(integer_scale((1920, 1080), world), 7);
+        assert_eq!(integer_scale((800, 600), world), 3);
+        assert_eq!(integer_scale((2000, 300), world), 2);
+
+        // Windows smaller than the world still draw at 1x
+        assert_eq!(integer_scale((100, 100), world), 1);
+    }
+
+    #[test]
+    fn verified_replays_hash_identically() {
+        let player = |x:f32| {
+            let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+            player.position = (x, (WORLD_HEIGHT - 8) as f32);
+            player
+        };
+        let path = temp_path("verified-recording.ron");
+        let _ = fs::remove_file(&path);
+
+        let mut world = test_world(vec![player(20.0)]);
+        world.start_recording(&path).unwrap();
+        for frame in 0..30 {
+            world.apply_input(InputState { left: frame > 20, right: frame < 15, jump: frame == 3 });
+            world.physics_step(1.0 / 60.0);
+        }
+        world.stop_recording().unwrap();
+        let recorded: Vec<FrameRecord> = fs::read_to_string(&path).unwrap()
+            .lines()
+            .map(|line| ron::from_str(line).unwrap())
+            .collect();
+
+        let mut replay_world = test_world(vec![player(20.0)]);
+        replay_world.verify_replay = true;
+        let replayed = replay_world.replay(&path).unwrap();
+        let hashes = |records:&[FrameRecord]| records.iter().map(|record| record.state_hash).collect::<Vec<_>>();
+        assert_eq!(hashes(&replayed), hashes(&recorded));
+
+        // A different starting state diverges on the very first frame
+        let mut diverging_world = test_world(vec![player(21.0)]);
+        diverging_world.verify_replay = true;
+        let error = diverging_world.replay(&path).unwrap_err().to_string();
+        fs::remove_file(&path).unwrap();
+        assert!(error.contains("frame 1"), "{error}");
+    }
+
+    #[test]
+    fn hidden_layers_draw_nothing() {
+        let sprites = [(20.0, 1), (40.0, 1), (60.0, 2)].into_iter().map(|(x, layer)| {
+            let mut sprite = solid_sprite((8, 8), [255, 0, 0, 255]);
+            sprite.position = (x, 20.0);
+            sprite.layer = layer;
+            sprite
+        }).collect();
+        let mut world = test_world(sprites);
+
+        world.set_layer_visible(1, false);
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 20, 20), [0, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 40, 20), [0, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 60, 20), [255, 0, 0, 255]);
+
+        world.set_layer_visible(1, true);
+        world.set_layer_opacity(2, 0.0);
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 20, 20), [255, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 40, 20), [255, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 60, 20), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bilinear_sampling_blends_half_pixel_scrolls() {
+        let mut world = test_world(Vec::new());
+        let ramp = RgbaImage::from_fn(WORLD_WIDTH, WORLD_HEIGHT, |x, _| Rgba([(x % 16 * 10) as u8, 0, 0, 255]));
+        world.background_image = DynamicImage::ImageRgba8(ramp);
+        world.camera = (0.5, 0.0);
+
+        let nearest = draw_frame(&mut world);
+        world.background_sampling = BackgroundSampling::Bilinear;
+        let bilinear = draw_frame(&mut world);
+
+        assert_eq!(pixel(&world, &nearest, 2, 10), [20, 0, 0, 255]);
+        // Halfway between the texels at 2 and 3
+        assert_eq!(pixel(&world, &bilinear, 2, 10), [25, 0, 0, 255]);
+        assert_ne!(nearest, bilinear);
+    }
+
+    #[test]
+    fn benchmark_reports_every_frame() {
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (20.0, 20.0);
+        let mut world = test_world(vec![player]);
+
+        let report = world.benchmark(10).unwrap();
+        assert_eq!(report.frames, 10);
+        assert!(report.total > Duration::ZERO, "{report:?}");
+        assert!(report.max <= report.total, "{report:?}");
+        assert!(report.avg <= report.max, "{report:?}");
+        assert_eq!(report.avg, report.total / 10);
+        // The world really was stepped, the player fell
+        assert!(world.sprites[0].position.1 > 20.0);
+    }
 
-fn get_current_time() -> u128 {
-    match SystemTime::now().duration_since(UNIX_EPOCH) {
-        Ok(n) => return n.as_millis(),
-        Err(_) => panic!("SystemTime before UNIX EPOCH!"),
+    #[test]
+    fn restitution_halves_each_bounce() {
+        let mut ball = solid_sprite((8, 8), [255, 0, 0, 255]);
+        ball.position = (20.0, 0.0);
+        let mut world = test_world(vec![ball]);
+        world.physics.restitution = 0.5;
+        let floor = world.floor_position(&world.sprites[0]);
+
+        // The speed the ball leaves the floor with after each landing
+        let mut launches = Vec::new();
+        for _ in 0..2000 {
+            let falling = world.sprites[0].velocity.1 > 0.0;
+            world.physics_step(1.0 / 60.0);
+            if falling && world.sprites[0].velocity.1 < 0.0 {
+                launches.push(-world.sprites[0].velocity.1);
+            }
+        }
+
+        assert!(launches.len() >= 4, "{launches:?}");
+        // Gravity during the landing step nudges later bounces up a little
+        for pair in launches[..4].windows(2) {
+            let ratio = pair[1] / pair[0];
+            assert!((0.45..0.6).contains(&ratio), "{launches:?}");
+        }
+        assert_eq!(world.sprites[0].velocity.1, 0.0);
+        assert_eq!(world.sprites[0].position.1, floor);
     }
-}
 
-impl Sprite {
-    fn new(sprite_sheet:SpriteSheet) -> Self {
-        Self {
-            size: sprite_sheet.frame_size,
-            sprite_sheet,
-            facing_left: false,
+    #[test]
+    fn overlay_blends_the_second_sheet_on_top() {
+        let sheet = |texture:RgbaImage| SpriteSheet::new(DynamicImage::ImageRgba8(texture), vec![Animation::new((0, 0), 1, 100)], (8, 8));
+        let base = sheet(RgbaImage::from_pixel(8, 8, Rgba([0, 0, 255, 255])));
+        // Half-transparent red over the left half, nothing over the right
+        let armor = sheet(RgbaImage::from_fn(8, 8, |x, _| if x < 4 { Rgba([255, 0, 0, 128]) } else { Rgba([0, 0, 0, 0]) }));
 
-            // Physics information
-            // TODO: move this to its own struct
-            position: (0.0, 0.0),
-            velocity: (0.0, 0.0),
+        let combined = base.overlay(&armor).texture.to_rgba8();
+        assert_eq!(combined.dimensions(), (8, 8));
+        let blended = combined.get_pixel(1, 1).0;
+        // Within a step of rounding of half red, half blue, still opaque
+        let expected = [128, 0, 127, 255];
+        assert!(blended.iter().zip(expected).all(|(&channel, expected)| channel.abs_diff(expected) <= 1), "{blended:?}");
+        assert_eq!(combined.get_pixel(6, 1).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn render_scale_doubles_the_output_not_the_world() {
+        // 8 world pixels wide, 16 output pixels at 2x
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (10.0, (WORLD_HEIGHT - 8) as f32);
+        let mut world = test_world(vec![player]);
+        world.render_scale = 2;
+        for _ in 0..10 {
+            world.physics_step(1.0 / 60.0);
         }
+        // Physics still happens in world units, the player rests on the world's floor
+        assert_eq!(world.sprites[0].position, (10.0, (WORLD_HEIGHT - 8) as f32));
+
+        let frame = draw_frame(&mut world);
+        assert_eq!(frame.len(), (WORLD_WIDTH * 2 * WORLD_HEIGHT * 2 * 4) as usize);
+        let top = (WORLD_HEIGHT - 8) * 2;
+        assert_eq!(pixel(&world, &frame, 19, top), [0, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 20, top), [255, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 35, top + 15), [255, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 36, top), [0, 0, 0, 255]);
     }
 
-    fn collision_y(&mut self) -> bool {
-        if self.position.1 as u16 + self.size.0 >= WORLD_HEIGHT as u16 {
-            return true;
+    #[test]
+    fn opacity_tween_is_half_way_at_its_midpoint() {
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (20.0, (WORLD_HEIGHT - 8) as f32);
+        let mut world = test_world(vec![player, solid_sprite((8, 8), [0, 0, 255, 255])]);
+        world.add_tween(Tween::new(0, TweenField::Opacity, 0.0, 1.0, 1.0, Easing::Linear));
+        world.add_tween(Tween::new(1, TweenField::Opacity, 0.0, 1.0, 1.0, Easing::EaseInOut));
+        assert_eq!(world.sprites[0].opacity, 0.0);
+
+        for _ in 0..15 {
+            world.physics_step(1.0 / 60.0);
         }
-        return false;
+        // A quarter of the way through, smoothstep gives 0.25² × (3 - 0.5)
+        assert!((world.sprites[1].opacity - 0.15625).abs() < 1e-3, "{}", world.sprites[1].opacity);
+
+        for _ in 0..15 {
+            world.physics_step(1.0 / 60.0);
+        }
+        assert!((world.sprites[0].opacity - 0.5).abs() < 1e-3, "{}", world.sprites[0].opacity);
+        assert!((world.sprites[1].opacity - 0.5).abs() < 1e-3, "{}", world.sprites[1].opacity);
+
+        for _ in 0..40 {
+            world.physics_step(1.0 / 60.0);
+        }
+        assert_eq!((world.sprites[0].opacity, world.sprites[1].opacity), (1.0, 1.0));
+        assert!(world.tweens.is_empty());
     }
 
-    fn run_animation(&mut self) {
-        let frame_size = self.sprite_sheet.frame_size;
-        let sheet_width = self.sprite_sheet.sheet_dimensions.0;
-        let current = self.sprite_sheet.current_animation;
+    #[test]
+    fn animation_progress_tracks_the_current_frame() {
+        let mut world = test_world(vec![strip_sprite(4, 4)]);
+        world.clock_mode = ClockMode::Ticks;
 
-        self.sprite_sheet.animations[current].increment_frame(frame_size, sheet_width);
+        let mut samples = Vec::new();
+        for _ in 0..6 {
+            world.update_sprite_animations();
+            let sprite = &world.sprites[0];
+            let animation = &sprite.sprite_sheet.animations[sprite.sprite_sheet.current_animation];
+            samples.push((animation.current_frame_index(), sprite.animation_progress()));
+        }
+        // Four ticks per frame, the second frame starting on the fourth tick
+        assert_eq!(samples, [(0, 0.25), (0, 0.5), (0, 0.75), (1, 0.0), (1, 0.25), (1, 0.5)]);
     }
 
-    fn get_sheet_offset(&self) -> (u16, u16) {
-        let current = self.sprite_sheet.current_animation;
-        return self.sprite_sheet.animations[current].current_position;
+    #[test]
+    fn static_background_is_copied_from_its_cache() {
+        let mut sprite = solid_sprite((8, 8), [255, 0, 0, 255]);
+        sprite.position = (40.0, 20.0);
+        let mut world = test_world(vec![sprite]);
+        world.static_background = true;
+        let mut frame = draw_frame(&mut world);
+        let first = frame.clone();
+
+        // Change the texture behind the engine's back, the cached copy is still what gets drawn
+        let white = RgbaImage::from_pixel(WORLD_WIDTH, WORLD_HEIGHT, Rgba([255, 255, 255, 255]));
+        world.background_image = DynamicImage::ImageRgba8(white);
+        world.draw(&mut frame).unwrap();
+        assert_eq!(frame, first);
+        assert_eq!(pixel(&world, &frame, 40, 20), [255, 0, 0, 255]);
+
+        // Moving the camera invalidates the cache and resamples the background
+        world.camera = (1.0, 0.0);
+        world.draw(&mut frame).unwrap();
+        assert_eq!(pixel(&world, &frame, 10, 10), [255, 255, 255, 255]);
     }
 
-    fn get_sprite_sheet(&self) -> &DynamicImage {
-        return &self.sprite_sheet.texture;
+    #[test]
+    fn static_background_only_restores_around_sprites() {
+        let mut sprite = solid_sprite((8, 8), [255, 0, 0, 255]);
+        sprite.position = (40.0, 20.0);
+        let mut world = test_world(vec![sprite]);
+        world.static_background = true;
+        let mut frame = draw_frame(&mut world);
+
+        // Mark a pixel away from the sprite, then move the sprite
+        let marker = [0, 255, 0, 255];
+        let (width, _) = world.render_size();
+        let index = ((100 * width + 200) * 4) as usize;
+        frame[index..index + 4].copy_from_slice(&marker);
+        world.sprites[0].position = (43.0, 22.0);
+        world.draw(&mut frame).unwrap();
+
+        // Only the old and new boxes were copied back, so the mark outside them is untouched
+        assert_eq!(pixel(&world, &frame, 200, 100), marker);
+        assert_eq!(pixel(&world, &frame, 40, 20), [0, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 50, 29), [255, 0, 0, 255]);
     }
 
-    fn flip_sprite(&mut self) -> bool {
-        if self.facing_left == false {
-            self.facing_left = true;
-        } else {
-            self.facing_left = false;
-        }
+    #[test]
+    fn sort_policy_breaks_ties_and_pinned_sprites_go_last() {
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255, 255, 0, 255]];
+        let sprites: Vec<Sprite> = colors.into_iter().map(|color| {
+            let mut sprite = solid_sprite((8, 8), color);
+            sprite.position = (40.0, 20.0);
+            sprite
+        }).collect();
+        let mut world = test_world(sprites);
+        world.sprites[1].draw_priority = Some(0);
+        world.sprites[3].z_index = 5;
+
+        assert_eq!(world.draw_order(), [0, 2, 3, 1]);
+        world.sort_policy = SortPolicy::ReverseSpawnOrder;
+        assert_eq!(world.draw_order(), [2, 0, 3, 1]);
 
-        return true;
+        // The pinned sprite is on top even over a higher z_index
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 42, 22), [0, 255, 0, 255]);
     }
 
-    fn facing_left(&self) -> bool {
-        return self.facing_left;
+    #[test]
+    fn dirty_rect_bounds_a_moved_sprite() {
+        let mut sprite = solid_sprite((8, 8), [255, 0, 0, 255]);
+        sprite.position = (40.0, 20.0);
+        let mut world = test_world(vec![sprite]);
+        let (width, height) = world.render_size();
+        let mut frame = vec![0; (width * height * 4) as usize];
+
+        assert_eq!(world.draw_with_dirty(&mut frame).unwrap(), Some(Rect::new(0, 0, width, height)));
+        assert_eq!(world.draw_with_dirty(&mut frame).unwrap(), None);
+
+        // Moving 3 pixels right and 2 down changes the union of the old and new boxes
+        world.sprites[0].position = (43.0, 22.0);
+        assert_eq!(world.draw_with_dirty(&mut frame).unwrap(), Some(Rect::new(40, 20, 11, 10)));
     }
-}
 
-impl SpriteSheet {
-    fn new(texture:DynamicImage, animations:Vec<Animation>, frame_size:(u16, u16)) -> Self {
-        let height = texture.height();
-        let width = texture.width();
-        Self {
-            texture,
-            frame_size,
-            animations,
-            current_animation: 0,
-            sheet_dimensions: (width as u16, height as u16),
+    #[test]
+    fn angular_velocity_spins_sprites() {
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (20.0, (WORLD_HEIGHT - 8) as f32);
+        let mut coin = solid_sprite((8, 8), [255, 255, 0, 255]);
+        coin.angular_velocity = std::f32::consts::PI;
+        let mut world = test_world(vec![player, coin]);
+
+        for _ in 0..30 {
+            world.physics_step(1.0 / 60.0);
         }
+        let rotation = world.sprites[1].rotation;
+        assert!((rotation - std::f32::consts::FRAC_PI_2).abs() < 1e-4, "{rotation}");
+        assert_eq!(world.sprites[0].rotation, 0.0);
+
+        // A full second later it's turned another half turn
+        for _ in 0..60 {
+            world.physics_step(1.0 / 60.0);
+        }
+        let rotation = world.sprites[1].rotation;
+        assert!((rotation - 3.0 * std::f32::consts::FRAC_PI_2).abs() < 1e-4, "{rotation}");
     }
-}
 
-/// Create a new animation instance
-impl Animation {
-    fn new(starting_frame_position:(u16, u16), num_frames:u16, frame_duration:u64) -> Self {
-        Self {
-            starting_frame_position,
-            num_frames,
-            frame_duration,
-            current_frame: 0,
-            current_position: starting_frame_position,
-            previous_frame_time: 0,
+    #[test]
+    fn letterbox_bars_use_the_configured_color() {
+        let mut world = test_world(Vec::new());
+        world.letterbox_color = [30, 60, 90, 255];
+
+        // A 10x4 surface with the content in the middle 6 columns, bars of 2 on either side
+        let mut frame = vec![7; 10 * 4 * 4];
+        world.fill_letterbox(&mut frame, 10, Rect::new(2, 0, 6, 4));
+        for (i, pixel) in frame.chunks_exact(4).enumerate() {
+            let x = i % 10;
+            let expected = if (2..8).contains(&x) { [7, 7, 7, 7] } else { [30, 60, 90, 255] };
+            assert_eq!(pixel, expected, "pixel {x},{}", i / 10);
         }
+
+        let clear = world.letterbox_clear_color();
+        assert_eq!((clear.r, clear.g, clear.b, clear.a), (30.0 / 255.0, 60.0 / 255.0, 90.0 / 255.0, 1.0));
     }
 
-    /// If the duration has elapsed, increment the currently selected animation by 1 frame
-    fn increment_frame(&mut self, frame_size:(u16, u16), sheet_width:u16) {
-        if self.frame_duration == 0 {
-            return;
+    #[test]
+    fn strips_step_one_frame_width_at_a_time() {
+        let texture = RgbaImage::from_fn(40, 6, |x, _| Rgba([(x / 10 + 1) as u8 * 50, 0, 0, 255]));
+        let sheet = SpriteSheet::from_strip(DynamicImage::ImageRgba8(texture), 10, 4, 1);
+        assert_eq!(sheet.frame_size, (10, 6));
+        let mut world = test_world(vec![Sprite::new(sheet)]);
+        world.clock_mode = ClockMode::Ticks;
+
+        let mut offsets = Vec::new();
+        for _ in 0..6 {
+            world.update_sprite_animations();
+            offsets.push(world.sprites[0].get_sheet_offset());
         }
+        // Straight along the strip and back to the start, never down a row
+        assert_eq!(offsets, [(10, 0), (20, 0), (30, 0), (0, 0), (10, 0), (20, 0)]);
 
-        let current_time = get_current_time() as i128;
+        // Drawing ticks the clock on to the last frame
+        world.sprites[0].position = (40.0, 20.0);
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 40, 20), [200, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 49, 25), [200, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 50, 20), [0, 0, 0, 255]);
+    }
 
-        // Only increment the frame if time has elapsed
-        if current_time - self.previous_frame_time < self.frame_duration as i128 {
-            return;
+    #[test]
+    fn next_animation_cycles_and_wraps() {
+        let texture = DynamicImage::ImageRgba8(RgbaImage::new(32, 24));
+        let animations = (0..3).map(|row| Animation::new((0, row * 8), 4, 100)).collect();
+        let mut sprite = Sprite::new(SpriteSheet::new(texture, animations, (8, 8)));
+
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            sprite.next_animation();
+            seen.push(sprite.sprite_sheet.current_animation);
         }
+        assert_eq!(seen, [1, 2, 0, 1]);
 
-        if self.current_frame >= self.num_frames - 1 {
-            self.current_frame = 0;
-            self.current_position = self.starting_frame_position;
-        } else {
-            if self.current_position.0 + frame_size.0 * 2 >= sheet_width {
-                self.current_position = (0, self.current_position.1 + frame_size.1);
-            } else {
-                self.current_position = (self.current_position.0 + frame_size.0, self.current_position.1);
-            }
-            self.current_frame += 1;
+        let mut seen = Vec::new();
+        for _ in 0..3 {
+            sprite.prev_animation();
+            seen.push(sprite.sprite_sheet.current_animation);
         }
+        assert_eq!(seen, [0, 2, 1]);
 
-        self.previous_frame_time = current_time;
+        // Switching starts the new animation from its first frame
+        let mut world = test_world(vec![sprite]);
+        world.clock_mode = ClockMode::Ticks;
+        for _ in 0..250 {
+            world.update_sprite_animations();
+        }
+        assert_ne!(world.sprites[0].get_sheet_offset(), (0, 8));
+        world.sprites[0].next_animation();
+        assert_eq!(world.sprites[0].get_sheet_offset(), (0, 16));
     }
-}
 
-/// Create a new `World` instance that can draw sprites
-impl World {
-    fn new(sprites: Vec<Sprite>) -> Self {
-        Self {
-            right_held: false,
-            left_held: false,
-            background_image: image::open(&Path::new("assets/images/bg.png")).unwrap(),
-            sprites,
-        }
+    #[test]
+    fn captured_frames_match_the_buffer() {
+        let mut sprite = solid_sprite((8, 8), [255, 128, 0, 255]);
+        sprite.position = (40.0, 20.0);
+        let mut world = test_world(vec![sprite]);
+        let frame = draw_frame(&mut world);
+
+        let image = world.capture_frame(&frame).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), world.render_size());
+        assert_eq!(image.as_raw(), &frame);
+        assert_eq!(image.get_pixel(40, 20).0, [255, 128, 0, 255]);
+
+        assert!(world.capture_frame(&frame[4..]).is_err());
     }
 
-    /// Set the animation to use in the
-    fn set_sprite_animation(&mut self, sprite:usize, animation_index:usize) {
-        self.sprites[sprite].sprite_sheet.current_animation = animation_index;
+    #[test]
+    fn input_sequences_match_only_within_their_window() {
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (20.0, (WORLD_HEIGHT - 8) as f32);
+        let mut world = test_world(vec![player]);
+        let combo = [Action::Down, Action::Right, Action::Jump];
+
+        // Press each action of the combo `gap` steps apart
+        let press_combo = |world:&mut World, gap:u32| {
+            for action in combo {
+                world.record_press(action);
+                for _ in 0..gap {
+                    world.physics_step(1.0 / 60.0);
+                }
+            }
+        };
+
+        press_combo(&mut world, 3);
+        assert!(world.matches_sequence(&combo, 10));
+        assert!(!world.matches_sequence(&[Action::Right, Action::Down, Action::Jump], 10));
+
+        press_combo(&mut world, 20);
+        assert!(!world.matches_sequence(&combo, 10));
+        assert!(world.matches_sequence(&combo, 60));
     }
 
-    /// Update held/released keys
-    fn key_held(&mut self, key_id:VirtualKeyCode) {
-        if key_id == VirtualKeyCode::Right {
-            self.right_held = true;
+    #[test]
+    fn bilinear_sprites_blend_between_texels() {
+        let gradient = RgbaImage::from_fn(2, 2, |x, _| Rgba([x as u8 * 200, 0, 0, 255]));
+        let mut sprite = Sprite::new(SpriteSheet::new(DynamicImage::ImageRgba8(gradient), vec![Animation::new((0, 0), 1, 0)], (2, 2)));
+        sprite.position = (40.0, 20.0);
+        sprite.scale = (2.0, 2.0);
+        let mut world = test_world(vec![sprite]);
+
+        let row = |world:&mut World| {
+            let frame = draw_frame(world);
+            (40..44).map(|x| pixel(world, &frame, x, 21)[0]).collect::<Vec<_>>()
+        };
+        assert_eq!(row(&mut world), [0, 0, 200, 200]);
+        world.sprites[0].filter = SampleFilter::Bilinear;
+        assert_eq!(row(&mut world), [0, 50, 150, 200]);
+    }
+
+    #[test]
+    fn animated_tiles_repeat_the_current_frame() {
+        // First frame half red and half blue, second frame all green
+        let texture = RgbaImage::from_fn(16, 8, |x, _| match x {
+            0..=3 => Rgba([255, 0, 0, 255]),
+            4..=7 => Rgba([0, 0, 255, 255]),
+            _ => Rgba([0, 255, 0, 255]),
+        });
+        let sheet = SpriteSheet::new(DynamicImage::ImageRgba8(texture), vec![Animation::new((0, 0), 2, 2)], (8, 8));
+        let mut world = test_world(Vec::new());
+        world.background = Background::AnimatedTile(sheet);
+        world.clock_mode = ClockMode::Ticks;
+
+        let frame = draw_frame(&mut world);
+        for (x, y, color) in [(0, 0, [255, 0, 0, 255]), (5, 3, [0, 0, 255, 255]), (8, 8, [255, 0, 0, 255]), (101, 50, [0, 0, 255, 255])] {
+            assert_eq!(pixel(&world, &frame, x, y), color, "pixel {x},{y}");
         }
-        if key_id == VirtualKeyCode::Left {
-            self.left_held = true;
+
+        // The next tick moves every tile on to the second frame
+        let frame = draw_frame(&mut world);
+        for (x, y) in [(0, 0), (5, 3), (8, 8), (101, 50)] {
+            assert_eq!(pixel(&world, &frame, x, y), [0, 255, 0, 255], "pixel {x},{y}");
         }
     }
 
-    fn key_released(&mut self, key_id:VirtualKeyCode) {
-        if key_id == VirtualKeyCode::Right {
-            self.right_held = false;
-        }
-        if key_id == VirtualKeyCode::Left {
-            self.left_held = false;
+    #[test]
+    fn offscreen_projectiles_are_despawned() {
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (20.0, (WORLD_HEIGHT - 8) as f32);
+        let mut world = test_world(vec![player]);
+
+        let mut projectile = solid_sprite((4, 4), [255, 255, 0, 255]);
+        projectile.position = (WORLD_WIDTH as f32 - 10.0, 20.0);
+        projectile.velocity = (30.0, 0.0);
+        projectile.despawn_offscreen = true;
+        let projectile = world.spawn(projectile).unwrap();
+        let coin = world.spawn(solid_sprite((4, 4), [0, 255, 255, 255])).unwrap();
+        world.add_tween(Tween::new(coin, TweenField::Opacity, 0.0, 1.0, 10.0, Easing::Linear));
+
+        // Still around while inside the margin past the edge
+        world.physics_step(1.0 / 60.0);
+        assert_eq!(world.sprites.len(), 3);
+        assert!(world.sprites[projectile].position.0 > WORLD_WIDTH as f32 - 10.0);
+
+        for _ in 0..20 {
+            world.physics_step(1.0 / 60.0);
         }
+        assert_eq!(world.sprites.len(), 2);
+        // The coin moved down into the projectile's slot and its tween followed it
+        assert_eq!(world.tweens[0].sprite, 1);
+        assert!(world.sprites[1].opacity > 0.0);
     }
 
-    fn set_velocity_y(&mut self, velocity:f32, sprite_index:usize) {
-        self.sprites[sprite_index].velocity.1 = velocity;
+    #[test]
+    fn play_looks_animations_up_by_name() {
+        let texture = DynamicImage::ImageRgba8(RgbaImage::new(32, 16));
+        let animations = vec![
+            Animation::new((0, 0), 4, 100).named("idle"),
+            Animation::new((0, 8), 4, 100).named("run"),
+        ];
+        let mut sprite = Sprite::new(SpriteSheet::new(texture, animations, (8, 8)));
+
+        sprite.play("run").unwrap();
+        assert_eq!(sprite.sprite_sheet.current_animation, 1);
+        assert_eq!(sprite.get_sheet_offset(), (0, 8));
+        sprite.play("idle").unwrap();
+        assert_eq!(sprite.sprite_sheet.current_animation, 0);
+
+        assert_eq!(sprite.play("jump"), Err(AnimationNotFound("jump".to_string())));
+        assert_eq!(sprite.sprite_sheet.current_animation, 0);
     }
 
-    fn update_movement(&mut self) {
-        if self.right_held && self.sprites[0].velocity.0.abs() < 4.75 {
-            self.sprites[0].velocity.0 += 0.3;
+    #[test]
+    fn slopes_lift_the_player_one_pixel_per_pixel() {
+        // Flat ground at y 128 up to a ramp in column 4, then flat ground 16 pixels higher
+        let columns = (WORLD_WIDTH / 16) as usize;
+        let mut tiles = vec![TileShape::Empty; columns * 9];
+        for column in 0..columns {
+            tiles[7 * columns + column] = match column {
+                0..=3 => TileShape::Empty,
+                4 => TileShape::SlopeUp,
+                _ => TileShape::Solid,
+            };
+            if column < 4 {
+                tiles[8 * columns + column] = TileShape::Solid;
+            }
         }
 
-        if self.left_held && self.sprites[0].velocity.0.abs() < 4.75 {
-            self.sprites[0].velocity.0 -= 0.3;
-        }
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (30.0, 120.0);
+        let mut world = test_world(vec![player]);
+        world.collision_map = Some(CollisionMap { tile_size: 16, width: columns, tiles });
 
-        // Smooth out floating point errors
-        self.sprites[0].velocity.0 = (self.sprites[0].velocity.0 * 1000.0).round() / 1000.0;
-        if self.sprites[0].velocity.0.abs() < 0.3 {
-            self.sprites[0].velocity.0 = 0.0;
+        let mut samples = Vec::new();
+        for _ in 0..200 {
+            world.apply_input(InputState { left: false, right: true, jump: false });
+            world.physics_step(1.0 / 60.0);
+            let player = &world.sprites[0];
+            let (center_x, feet) = (player.position.0 + 4.0, player.position.1 + 8.0);
+            if (64.0..80.0).contains(&center_x) {
+                samples.push((center_x, feet));
+            }
         }
 
-        if self.sprites[0].velocity.0 < 0.0 {
-            self.sprites[0].facing_left = true;
-        } else {
-            self.sprites[0].facing_left = false;
+        assert!(samples.len() > 3, "{samples:?}");
+        for &(center_x, feet) in &samples {
+            assert!((feet - (128.0 - (center_x - 64.0))).abs() < 1e-3, "{samples:?}");
         }
+        // Walked over the top of the ramp onto the higher ground
+        assert_eq!(world.sprites[0].position.1 + 8.0, 112.0);
     }
 
-    // TODO this is bad, completely refactor
-    fn update_physics(&mut self) {
-        let acceleration_y:f32 = 0.1;
-        let mut friction_x:f32 = 0.01;
+    #[test]
+    fn player_bounds_stop_the_player_at_their_edge() {
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (20.0, 20.0);
+        let mut world = test_world(vec![player]);
+        // Keep the bottom 24 rows free for a HUD
+        world.player_bounds = Some(Rect::new(10, 0, 100, WORLD_HEIGHT - 24));
 
-        if self.sprites[0].collision_y() {
-            friction_x = 0.1;
+        for _ in 0..300 {
+            world.apply_input(InputState { left: false, right: true, jump: false });
+            world.physics_step(1.0 / 60.0);
         }
+        let player = &world.sprites[0];
+        assert_eq!(player.position, (102.0, (WORLD_HEIGHT - 32) as f32));
+        assert_eq!(player.velocity.0, 0.0);
 
-        // Move the sprite
-        self.sprites[0].position.0 += self.sprites[0].velocity.0 / 5.0;
-        self.sprites[0].position.1 += self.sprites[0].velocity.1 / 5.0;
-
-        // Define the screen bounds
-        if self.sprites[0].position.0 as i16 <= 0 || self.sprites[0].position.0 as u16 + self.sprites[0].size.0 > WORLD_WIDTH as u16 {
-            self.sprites[0].velocity.0 = 0.0;
-        } else if self.sprites[0].velocity.0 > 0.0 {
-            self.sprites[0].velocity.0 -= friction_x;
-        } else if self.sprites[0].velocity.0 < 0.0 {
-            self.sprites[0].velocity.0 += friction_x;
+        for _ in 0..300 {
+            world.apply_input(InputState { left: true, right: false, jump: false });
+            world.physics_step(1.0 / 60.0);
         }
+        assert_eq!(world.sprites[0].position.0, 10.0);
+    }
 
-        self.sprites[0].position.0 = (self.sprites[0].position.0 * 100.0).round() / 100.0;
+    #[test]
+    fn pixel_at_reads_world_positions() {
+        let mut lava = solid_sprite((8, 8), [255, 64, 0, 255]);
+        lava.position = (40.0, 20.0);
+        let mut world = test_world(vec![lava]);
+        world.camera = (10.0, 0.0);
+        world.render_scale = 2;
+        let frame = draw_frame(&mut world);
 
-        if self.sprites[0].position.1 as u16 + self.sprites[0].size.1 < WORLD_HEIGHT as u16 && self.sprites[0].velocity.1 < 5.0 {
-            self.sprites[0].velocity.1 += acceleration_y;
-        } else if self.sprites[0].collision_y() {
-            self.sprites[0].position.1 = WORLD_HEIGHT as f32 - self.sprites[0].size.1 as f32;
-            self.sprites[0].velocity.1 = 0.0;
-        }
+        // World coordinates, not screen ones, whatever the camera and render scale
+        assert_eq!(world.pixel_at(&frame, 42, 22), Some([255, 64, 0, 255]));
+        assert_eq!(world.pixel_at(&frame, 48, 22), Some([0, 0, 0, 255]));
+        assert_eq!(pixel(&world, &frame, 60, 44), [255, 64, 0, 255]);
+
+        // Off the left of the screen, and past the bottom of the world
+        assert_eq!(world.pixel_at(&frame, 5, 22), None);
+        assert_eq!(world.pixel_at(&frame, 42, WORLD_HEIGHT), None);
     }
 
-    /// Update all sprite frames
-    fn update_sprite_animations(&mut self) {
-        for i in 0..self.sprites.len() {
-            self.sprites[i].run_animation();
-        }
+    #[test]
+    fn idle_timeout_switches_animation_until_moving() {
+        let texture = DynamicImage::ImageRgba8(RgbaImage::new(16, 16));
+        let animations = vec![Animation::new((0, 0), 2, 100), Animation::new((0, 8), 2, 100)];
+        let mut player = Sprite::new(SpriteSheet::new(texture, animations, (8, 8)));
+        player.position = (20.0, (WORLD_HEIGHT - 8) as f32);
+        player.idle_timeout_ms = Some(500);
+        player.idle_animation = 1;
+        let mut world = test_world(vec![player]);
+
+        let steps = |world:&mut World, count:u32, input:InputState| {
+            for _ in 0..count {
+                world.apply_input(input);
+                world.physics_step(1.0 / 60.0);
+            }
+            world.sprites[0].sprite_sheet.current_animation
+        };
+        let still = InputState::default();
+        let walking = InputState { left: false, right: true, jump: false };
+
+        assert_eq!(steps(&mut world, 25, still), 0);
+        assert_eq!(steps(&mut world, 10, still), 1);
+        // Moving switches straight back and starts the wait over
+        assert_eq!(steps(&mut world, 1, walking), 0);
+        assert_eq!(steps(&mut world, 25, still), 0);
+        assert_eq!(steps(&mut world, 10, still), 1);
     }
 
-    /// Draw the updated state of all sprites and background to the frame buffer.
-    fn draw(&mut self, frame: &mut [u8]) -> Result<(), Box<dyn std::error::Error>> {
+    #[test]
+    fn motion_blur_leaves_a_half_bright_trail() {
+        let mut sprite = solid_sprite((8, 8), [200, 0, 0, 255]);
+        sprite.position = (40.0, 20.0);
+        let mut world = test_world(vec![sprite]);
+        world.motion_blur = 0.5;
+        draw_frame(&mut world);
 
-        // Run any animation updates
-        self.update_sprite_animations();
+        world.sprites[0].position = (60.0, 20.0);
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 42, 22), [100, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 62, 22), [100, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 80, 22), [0, 0, 0, 255]);
 
-        // Draw the background
-        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-            let x = (i % WORLD_WIDTH as usize) as u16;
-            let y = (i / WORLD_WIDTH as usize) as u16;
+        // The trail keeps fading while the sprite stays put
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 42, 22), [50, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 62, 22), [150, 0, 0, 255]);
+    }
 
-            let background_pixel = self.background_image.get_pixel(x as u32, y as u32);
-            let rgba = background_pixel.0;
+    #[test]
+    fn anchored_ui_sprites_sit_in_from_their_corner() {
+        let mut world = test_world(Vec::new());
+        world.camera = (30.0, 10.0);
+        let mut health = solid_sprite((10, 6), [0, 255, 0, 255]);
+        health.screen_anchor = Some(Anchor::BottomRight);
+        health.position = (4.0, 4.0);
+        let health = world.add_ui_sprite(health);
 
-            pixel.copy_from_slice(&rgba);
+        let expected = ((WORLD_WIDTH - 14) as i32, (WORLD_HEIGHT - 10) as i32);
+        assert_eq!(world.ui_screen_position(&world.ui_sprites[health]), expected);
+
+        // Drawn there whatever the camera is doing
+        let frame = draw_frame(&mut world);
+        let (x, y) = (expected.0 as u32, expected.1 as u32);
+        assert_eq!(pixel(&world, &frame, x, y), [0, 255, 0, 255]);
+        assert_eq!(pixel(&world, &frame, x + 9, y + 5), [0, 255, 0, 255]);
+        assert_eq!(pixel(&world, &frame, x + 10, y + 6), [0, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, x - 1, y), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn prototypes_spawn_sprites_sharing_a_texture() {
+        let mut world = test_world(vec![solid_sprite((8, 8), [255, 0, 0, 255])]);
+        let mut enemy = SpritePrototype::new(solid_sprite((8, 8), [0, 0, 255, 255]).sprite_sheet);
+        enemy.velocity = (-5.0, 0.0);
+        enemy.z_index = 3;
+
+        let positions = [(40.0, 20.0), (60.0, 30.0), (80.0, 40.0)];
+        let spawned: Vec<usize> = positions.iter().map(|&position| world.spawn_from(&enemy, position).unwrap()).collect();
+        assert_eq!(spawned, [1, 2, 3]);
+
+        for (&i, position) in spawned.iter().zip(positions) {
+            let sprite = &world.sprites[i];
+            assert_eq!(sprite.position, position);
+            assert_eq!((sprite.velocity, sprite.z_index), ((-5.0, 0.0), 3));
+            assert!(Rc::ptr_eq(&sprite.sprite_sheet.texture, &enemy.sprite_sheet.texture));
         }
+    }
 
-        // Draw sprites on the background
-        for i in 0..self.sprites.len() {
-            let offset = self.sprites[i].get_sheet_offset();
+    #[test]
+    fn rumble_becomes_screen_shake_without_a_gamepad() {
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (20.0, (WORLD_HEIGHT - 8) as f32);
+        let mut world = test_world(vec![player]);
 
-            // Loop through all pixels in a sprite
-            for z in 0..(self.sprites[i].size.0 * self.sprites[i].size.1) as usize {
-                let x = (z % self.sprites[i].size.0 as usize) as u16;
-                let y = (z / self.sprites[i].size.0 as usize) as u16;
+        world.emit_feedback(FeedbackEvent::Rumble { intensity: 4.0, duration: 0.5 });
+        assert_eq!(world.screen_shake, Some(ScreenShake { intensity: 4.0, duration: 0.5, elapsed: 0.0 }));
 
-                let colors:Rgba<u8>;
+        // A weaker shake leaves the stronger one playing
+        world.emit_feedback(FeedbackEvent::ScreenShake { intensity: 1.0, duration: 2.0 });
+        assert_eq!(world.screen_shake.unwrap().intensity, 4.0);
 
-                // Get the current sprite's pixel
-                let x_coord;
-                if self.sprites[i].facing_left() {
-                    x_coord = -(x as i16) + self.sprites[i].size.0 as i16;
-                } else {
-                    x_coord = x as i16;
-                }
+        world.step(0.25);
+        assert_eq!(world.screen_shake.unwrap().offset_strength(), 2.0);
+        world.step(0.25);
+        assert_eq!(world.screen_shake, None);
 
-                colors = self.sprites[i].get_sprite_sheet().get_pixel(
-                    (x_coord + offset.0 as i16) as u32,
-                    (y + offset.1) as u32);
+        // Hit stop eats real time before the physics sees it
+        world.emit_feedback(FeedbackEvent::HitStop { duration: 0.1 });
+        assert_eq!(world.step(0.05), 0);
+        assert_eq!(world.step(0.05), 0);
+        assert!(world.step(0.05) > 0);
+    }
 
-                if colors[3] == 0 {
-                    continue;
-                }
+    #[test]
+    fn weighted_spawns_follow_their_weights() {
+        let prototype = |z_index:i32| {
+            let mut prototype = SpritePrototype::new(solid_sprite((4, 4), [255, 0, 0, 255]).sprite_sheet);
+            prototype.z_index = z_index;
+            prototype
+        };
+        let choices = [(prototype(1), 3.0), (prototype(2), 1.0)];
 
-                let viewport_y = (y as i32 + self.sprites[i].position.1 as i32) * WORLD_WIDTH as i32;
-                let viewport_x = x as i32 + self.sprites[i].position.0 as i32;
+        let spawn_many = |seed:u64| {
+            let mut world = test_world(Vec::new());
+            world.seed_rng(seed);
+            for _ in 0..4000 {
+                world.spawn_weighted(&choices, (40.0, 20.0)).unwrap();
+            }
+            world.sprites.iter().map(|sprite| sprite.z_index).collect::<Vec<_>>()
+        };
 
-                let index = ((viewport_y + viewport_x) * 4) as usize;
+        let picks = spawn_many(7);
+        let common = picks.iter().filter(|&&z_index| z_index == 1).count() as f32 / picks.len() as f32;
+        assert!((common - 0.75).abs() < 0.03, "picked the 3x prototype {common} of the time");
+        // The same seed picks the same way
+        assert_eq!(spawn_many(7), picks);
 
-                let world_pixel;
-                if index <= ((WORLD_WIDTH * WORLD_HEIGHT) * 4) as usize {
-                    world_pixel = &mut frame[index..index + 4];
-                } else {
-                    continue;
-                }
+        let mut world = test_world(Vec::new());
+        assert_eq!(world.spawn_weighted(&[], (0.0, 0.0)), Err(SpawnError::InvalidWeights));
+        assert_eq!(world.spawn_weighted(&[(prototype(1), 0.0)], (0.0, 0.0)), Err(SpawnError::InvalidWeights));
+    }
 
-                let mut output:[u8; 4] = [0, 0, 0, 0];
+    #[test]
+    fn update_priority_moves_parents_before_children() {
+        let chain = || {
+            let root = solid_sprite((8, 8), [255, 0, 0, 255]);
+            let mut grandchild = solid_sprite((2, 2), [0, 0, 255, 255]);
+            grandchild.attach_to(2, (0.0, -4.0));
+            let mut child = solid_sprite((4, 4), [0, 255, 0, 255]);
+            child.attach_to(0, (4.0, 0.0));
+            let mut world = test_world(vec![root, grandchild, child]);
+            world.sprites[0].position = (100.0, 50.0);
+            world
+        };
 
-                // Create proper alpha blending for each color
-                for c in 0..3 {
-                    output[c] +=
-                        (world_pixel[c] as f32 * (1.0 - (colors[3] as f32/255.0))) as u8  // Make the background blend
-                        + (colors[c] as f32 * (colors[3] as f32/255.0)) as u8;            // Make the foreground blend
-                }
+        // In spawn order the grandchild follows where its parent was last frame
+        let mut world = chain();
+        world.update_attachments();
+        assert_eq!(world.sprites[2].position, (104.0, 50.0));
+        assert_eq!(world.sprites[1].position, (0.0, -4.0));
+
+        let mut world = chain();
+        world.sprites[2].update_priority = -1;
+        assert_eq!(world.update_order(), [2, 0, 1]);
+        world.update_attachments();
+        assert_eq!(world.sprites[1].position, (104.0, 46.0));
+    }
 
-                let rgba = [output[0], output[1], output[2], 255];
+    #[test]
+    fn pixel_snap_holds_sprites_on_whole_world_pixels() {
+        let mut sprite = solid_sprite((8, 8), [255, 0, 0, 255]);
+        sprite.position = (40.0, 20.0);
+        let mut world = test_world(vec![sprite]);
+        world.render_scale = 4;
 
-                // Write the output to the buffer
-                world_pixel.copy_from_slice(&rgba);
-            }
+        // Leftmost output column the sprite covers after each 0.4 pixel step
+        let left_edges = |world:&mut World| {
+            (0..4).map(|step| {
+                world.sprites[0].position.0 = 40.0 + step as f32 * 0.4;
+                let frame = draw_frame(world);
+                (150..170).find(|&x| pixel(world, &frame, x, 84) == [255, 0, 0, 255]).unwrap()
+            }).collect::<Vec<_>>()
+        };
+
+        assert_eq!(left_edges(&mut world), [160, 161, 163, 164]);
+        world.sprites[0].pixel_snap = true;
+        assert_eq!(left_edges(&mut world), [160, 160, 160, 164]);
+        // Only the drawing snaps, the position keeps its fraction
+        assert_eq!(world.sprites[0].position.0, 40.0 + 3.0 * 0.4);
+    }
+
+    #[test]
+    fn collision_callbacks_fire_once_on_contact() {
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (20.0, (WORLD_HEIGHT - 8) as f32);
+        let mut wall = solid_sprite((8, 8), [0, 0, 255, 255]);
+        wall.position = (100.0, 20.0);
+        wall.layer = 3;
+        let mut bullet = solid_sprite((8, 8), [255, 255, 0, 255]);
+        bullet.position = (80.0, 20.0);
+        bullet.velocity = (5.0, 0.0);
+        bullet.layer = 4;
+        let mut world = test_world(vec![player, wall, bullet]);
+
+        let hits = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let sprite_hits = Rc::clone(&hits);
+        world.on_collision(1, 2, Box::new(move |a, b| sprite_hits.borrow_mut().push(("sprites", a, b))));
+        let layer_hits = Rc::clone(&hits);
+        world.on_layer_collision(4, 3, Box::new(move |a, b| layer_hits.borrow_mut().push(("layers", a, b))));
+
+        for _ in 0..24 {
+            world.physics_step(1.0 / 60.0);
         }
+        // Still overlapping, but each callback only ran when they first touched, with its own argument order
+        assert!(world.sprites[2].bounds().intersect(&world.sprites[1].bounds()).width > 0);
+        assert_eq!(*hits.borrow(), [("sprites", 1, 2), ("layers", 2, 1)]);
+    }
 
-        Ok(())
+    #[test]
+    fn frozen_frames_hold_until_unfrozen() {
+        let mut world = test_world(vec![strip_sprite(4, 2)]);
+        world.clock_mode = ClockMode::Ticks;
+        world.sprites[0].freeze_frame(2);
+
+        for _ in 0..10 {
+            world.update_sprite_animations();
+            assert_eq!(world.sprites[0].get_sheet_offset(), (16, 0));
+        }
+        let frame = draw_frame(&mut world);
+        assert_eq!(pixel(&world, &frame, 0, 0), [120, 0, 0, 255]);
+
+        world.sprites[0].unfreeze();
+        world.update_sprite_animations();
+        world.update_sprite_animations();
+        assert_eq!(world.sprites[0].get_sheet_offset(), (24, 0));
     }
-}
 
-fn main() -> Result<(), Error> {
-    env_logger::init();
-    let event_loop = EventLoop::new();
-    let mut input = WinitInputHelper::new();
-    let window = {
-        let size = LogicalSize::new(WORLD_WIDTH as f64 * 7.0, WORLD_HEIGHT as f64 * 7.0);
-        WindowBuilder::new()
-            .with_title("2D Rendering Test")
-            .with_inner_size(size)
-            .with_min_inner_size(size)
-            .with_max_inner_size(size)
-            .build(&event_loop)
-            .unwrap()
-    };
+    #[test]
+    fn split_screen_viewports_center_their_players() {
+        let mut one = solid_sprite((8, 8), [255, 0, 0, 255]);
+        one.position = (40.0, 20.0);
+        let mut two = solid_sprite((8, 8), [0, 0, 255, 255]);
+        two.position = (200.0, 100.0);
+        let mut world = test_world(vec![one, two]);
+        let half = WORLD_WIDTH / 2;
+        world.viewports = vec![
+            Viewport { rect: Rect::new(0, 0, half, WORLD_HEIGHT), camera: (0.0, 0.0), follow_sprite: Some(0) },
+            Viewport { rect: Rect::new(half as i32, 0, half, WORLD_HEIGHT), camera: (0.0, 0.0), follow_sprite: Some(1) },
+        ];
+        let frame = draw_frame(&mut world);
 
-    let window_size = window.inner_size();
-    let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
+        // Each sprite's 8x8 box sits around the middle of its own half, and nowhere else
+        let (left_center, right_center, middle) = (half / 2, half + half / 2, WORLD_HEIGHT / 2);
+        for (center, color) in [(left_center, [255, 0, 0, 255]), (right_center, [0, 0, 255, 255])] {
+            assert_eq!(pixel(&world, &frame, center - 4, middle - 4), color);
+            assert_eq!(pixel(&world, &frame, center + 3, middle + 3), color);
+            assert_eq!(pixel(&world, &frame, center - 5, middle), [0, 0, 0, 255]);
+            assert_eq!(pixel(&world, &frame, center + 4, middle), [0, 0, 0, 255]);
+        }
+        let colored = frame.chunks_exact(4).filter(|pixel| pixel != &[0, 0, 0, 255]).count();
+        assert_eq!(colored, 2 * 64);
+        assert_eq!(world.viewports[0].camera, (44.0 - left_center as f32, 24.0 - middle as f32));
+    }
 
-    let mut pixels = PixelsBuilder::new(WORLD_WIDTH, WORLD_HEIGHT, surface_texture)
-    .enable_vsync(true)
-    .build()?;
+    #[test]
+    fn downscaled_sheets_keep_frames_on_their_grid() {
+        // Four columns and two rows of 256 pixel frames, each a flat color of its own
+        let frame_color = |column:u32, row:u32| Rgba([(row * 4 + column) as u8 * 30, 100, 0, 255]);
+        let texture = RgbaImage::from_fn(1024, 512, |x, y| frame_color(x / 256, y / 256));
+        let animations = vec![Animation::new((0, 0), 8, 1)];
+        let sheet = SpriteSheet::new_downscaled(DynamicImage::ImageRgba8(texture), animations, (256, 256), 256).unwrap();
+        assert_eq!((sheet.texture.width(), sheet.texture.height()), (256, 128));
+        assert_eq!((sheet.frame_size, sheet.sheet_dimensions), ((64, 64), (256, 128)));
 
-    let player_idle = Animation::new((0, 0), 4, 200);
-    let player_slide = Animation::new((0, 74), 4, 100);
-    let player_animations = vec![player_idle, player_slide];
-    let player_sheet = SpriteSheet::new(image::open(&Path::new("assets/images/player_sheet.png")).unwrap(), player_animations, (50, 37));
-    let player = Sprite::new(player_sheet);
+        let mut world = test_world(vec![Sprite::new(sheet)]);
+        world.clock_mode = ClockMode::Ticks;
+        for expected in [(64, 0), (128, 0), (192, 0), (0, 64), (64, 64)] {
+            world.update_sprite_animations();
+            let sprite = &world.sprites[0];
+            let offset = sprite.get_sheet_offset();
+            assert_eq!(offset, expected);
+            let center = sprite.sprite_sheet.texture.get_pixel(offset.0 as u32 + 32, offset.1 as u32 + 32);
+            assert_eq!(center, frame_color(offset.0 as u32 / 64, offset.1 as u32 / 64));
+        }
 
-    let window_static = Animation::new((0, 0), 1, 0);
-    let window_animations = vec![window_static];
-    let window_sheet = SpriteSheet::new(image::open(&Path::new("assets/images/building.png")).unwrap(), window_animations, (98, 72));
-    let mut window_sprite = Sprite::new(window_sheet);
+        // 37 pixel tall frames can't shrink by any whole factor
+        let texture = DynamicImage::ImageRgba8(RgbaImage::new(200, 37));
+        assert!(SpriteSheet::new_downscaled(texture, vec![Animation::new((0, 0), 4, 100)], (50, 37), 100).is_err());
+    }
 
-    window_sprite.position = (100.0, (WORLD_HEIGHT - window_sprite.size.1 as u32) as f32);
+    #[test]
+    fn upward_gravity_rests_sprites_on_the_ceiling() {
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (40.0, 100.0);
+        let mut world = test_world(vec![player]);
+        world.physics.gravity = (0.0, -0.1);
 
-    let sprite_list: Vec<Sprite> = vec![player, window_sprite];
+        let mut events = Vec::new();
+        for _ in 0..300 {
+            world.physics_step(1.0 / 60.0);
+            events.extend(world.poll_events());
+        }
+        assert_eq!(world.sprites[0].position, (40.0, 0.0));
+        assert_eq!(world.sprites[0].velocity, (0.0, 0.0));
+        assert!(events.contains(&EngineEvent::HitBoundary { sprite: 0, side: Side::Top }), "{events:?}");
 
-    let mut world = World::new(sprite_list);
+        // Sideways gravity pulls the player into the right wall instead
+        world.physics.gravity = (0.1, 0.0);
+        for _ in 0..600 {
+            world.physics_step(1.0 / 60.0);
+        }
+        assert_eq!(world.sprites[0].position.0, (WORLD_WIDTH - 8) as f32);
+    }
 
-    event_loop.run(move |event, _, control_flow| {
+    #[test]
+    fn asset_report_lists_every_texture_once() {
+        let hero_path = temp_path("report-hero.png");
+        let tiles_path = temp_path("report-tiles.png");
+        RgbaImage::new(64, 32).save(&hero_path).unwrap();
+        RgbaImage::new(48, 48).save(&tiles_path).unwrap();
 
-        // Draw the current frame
-        if let Event::RedrawRequested(_) = event {
-            world.draw(pixels.get_frame_mut()).ok();
-            if let Err(err) = pixels.render() {
-                error!("pixels.render() failed: {err}");
-                *control_flow = ControlFlow::Exit;
-                return;
-            }
+        let hero = SpriteSheet::load(&hero_path, vec![Animation::new((0, 0), 4, 100)], (16, 32)).unwrap();
+        let tiles = SpriteSheet::load(&tiles_path, vec![Animation::new((0, 0), 1, 0)], (16, 16)).unwrap();
+        fs::remove_file(&hero_path).unwrap();
+        fs::remove_file(&tiles_path).unwrap();
+
+        // Two sprites share the hero sheet's texture, it's only counted once
+        let world = test_world(vec![Sprite::new(hero.clone()), Sprite::new(tiles), Sprite::new(hero)]);
+        let report = world.asset_report();
+        let summary: Vec<_> = report.iter().map(|asset| (asset.path.clone(), asset.width, asset.height, asset.bytes)).collect();
+        assert_eq!(summary, [
+            (Some(PathBuf::new()), WORLD_WIDTH, WORLD_HEIGHT, (WORLD_WIDTH * WORLD_HEIGHT * 4) as u64),
+            (Some(hero_path), 64, 32, 64 * 32 * 4),
+            (Some(tiles_path), 48, 48, 48 * 48 * 4),
+        ]);
+    }
+
+    #[test]
+    fn wrap_x_carries_every_sprite_across_the_edge() {
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (20.0, (WORLD_HEIGHT - 8) as f32);
+        let mut projectile = solid_sprite((4, 4), [255, 255, 0, 255]);
+        projectile.position = (WORLD_WIDTH as f32 - 2.0, 20.0);
+        projectile.velocity = (10.0, 0.0);
+        // Can't leave a wrapping world, so this does nothing
+        projectile.despawn_offscreen = true;
+        let mut world = test_world(vec![player, projectile]);
+        world.wrap_x = true;
+        world.physics_substeps = 4;
+
+        world.physics_step(1.0 / 60.0);
+        assert_eq!(world.sprites[1].position, (0.0, 20.0));
+
+        // Long after it would have left a world that doesn't wrap, it's still going round
+        for _ in 0..200 {
+            world.physics_step(1.0 / 60.0);
         }
+        assert_eq!(world.sprites.len(), 2);
+        let x = world.sprites[1].position.0;
+        assert!((0.0..WORLD_WIDTH as f32).contains(&x), "projectile at {x}");
+        assert_eq!(x, (200.0 * 2.0) % WORLD_WIDTH as f32);
+    }
 
-        // Handle input events
-        if input.update(&event) {
-            // Close events
-            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
-                *control_flow = ControlFlow::Exit;
-            }
+    #[test]
+    fn sideways_gravity_falls_and_lands_like_the_floor() {
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (200.0, 100.0);
+        let mut world = test_world(vec![player]);
+        world.physics.gravity = (-0.1, 0.0);
 
-            if input.key_pressed(VirtualKeyCode::Up) {
-                world.set_velocity_y(-7.0, 0);
-                world.set_sprite_animation(0, 0);
-            } else if input.key_released(VirtualKeyCode::Up) {
-                world.set_sprite_animation(0, 0);
-            }
+        let mut events = Vec::new();
+        let mut fastest: f32 = 0.0;
+        for _ in 0..600 {
+            world.physics_step(1.0 / 60.0);
+            events.extend(world.poll_events());
+            fastest = fastest.max(world.sprites[0].velocity.0.abs());
+        }
 
-            if input.key_pressed(VirtualKeyCode::Right) {
-                world.key_held(VirtualKeyCode::Right);
-            } else if input.key_released(VirtualKeyCode::Right) {
-                world.key_released(VirtualKeyCode::Right);
-            }
+        // Speed builds up from rest, stops at the terminal velocity and lands once on the left wall
+        assert!(fastest > 4.0 && fastest <= TERMINAL_VELOCITY + 0.001, "{fastest}");
+        assert_eq!(world.sprites[0].position, (0.0, 100.0));
+        assert_eq!(world.sprites[0].velocity, (0.0, 0.0));
+        assert_eq!(events, [EngineEvent::HitBoundary { sprite: 0, side: Side::Left }]);
+    }
 
-            if input.key_pressed(VirtualKeyCode::Left) {
-                world.key_held(VirtualKeyCode::Left);
-            } else if input.key_released(VirtualKeyCode::Left) {
-                world.key_released(VirtualKeyCode::Left);
-            }
+    #[test]
+    fn holding_into_a_wall_reports_it_once() {
+        let mut player = solid_sprite((8, 8), [255, 0, 0, 255]);
+        player.position = (200.0, 136.0);
+        let mut world = test_world(vec![player]);
 
-            // Resize the window
-            if let Some(size) = input.window_resized() {
-                if let Err(err) = pixels.resize_surface(size.width, size.height) {
-                    error!("pixels.resize_surface() failed: {err}");
-                    *control_flow = ControlFlow::Exit;
-                    return;
-                }
-            }
+        world.right_held = true;
+        for _ in 0..300 {
+            world.physics_step(1.0 / 60.0);
+        }
+        assert_eq!(world.sprites[0].position.0, (WORLD_WIDTH - 8) as f32);
+        assert_eq!(world.poll_events(), [EngineEvent::HitBoundary { sprite: 0, side: Side::Right }]);
 
-            // Update internal state and request a redraw
-            world.update_movement();
-            world.update_physics();
-            window.request_redraw();
+        // Leaving the wall and running back into it is a new hit
+        world.right_held = false;
+        world.left_held = true;
+        for _ in 0..30 {
+            world.physics_step(1.0 / 60.0);
         }
-    });
+        world.left_held = false;
+        world.right_held = true;
+        for _ in 0..300 {
+            world.physics_step(1.0 / 60.0);
+        }
+        assert_eq!(world.poll_events(), [EngineEvent::HitBoundary { sprite: 0, side: Side::Right }]);
+    }
+
+    #[test]
+    fn hi_res_sheets_rest_on_the_floor_and_draw_one_to_one() {
+        // A 16x16 sheet at 2 texels per world pixel, red and blue columns one texel wide
+        let texture = RgbaImage::from_fn(16, 16, |x, _| if x % 2 == 0 { Rgba([255, 0, 0, 255]) } else { Rgba([0, 0, 255, 255]) });
+        let sheet = SpriteSheet::new(DynamicImage::ImageRgba8(texture), vec![Animation::new((0, 0), 1, 0)], (16, 16));
+        let mut player = Sprite::new(sheet).hi_res(2);
+        player.position = (10.0, 100.0);
+        let mut world = test_world(vec![player]);
+        world.render_scale = 2;
+        for _ in 0..120 {
+            world.physics_step(1.0 / 60.0);
+        }
+
+        // The floor and collisions see the 8x8 world size, not the 16x16 sheet
+        assert_eq!(world.sprites[0].size, (8, 8));
+        assert_eq!(world.sprites[0].position, (10.0, (WORLD_HEIGHT - 8) as f32));
+        assert_eq!(world.sprites[0].bounds(), Rect::new(10, (WORLD_HEIGHT - 8) as i32, 8, 8));
+
+        // Every texel lands on its own output pixel, touching the bottom of the frame
+        let frame = draw_frame(&mut world);
+        let top = (WORLD_HEIGHT - 8) * 2;
+        assert_eq!(pixel(&world, &frame, 20, top), [255, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 21, top), [0, 0, 255, 255]);
+        assert_eq!(pixel(&world, &frame, 34, WORLD_HEIGHT * 2 - 1), [255, 0, 0, 255]);
+        assert_eq!(pixel(&world, &frame, 35, WORLD_HEIGHT * 2 - 1), [0, 0, 255, 255]);
+        assert_eq!(pixel(&world, &frame, 36, top), [0, 0, 0, 255]);
+    }
 }
@@ -0,0 +1,217 @@
+//! Helpers for locking down rendering output against golden images
+
+use image::{Rgba, RgbaImage};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::{InputState, World};
+
+/// Render a single frame of the world into a new image without a window
+pub fn render_headless(world: &mut World) -> Result<RgbaImage, Box<dyn std::error::Error>> {
+    let (width, height) = world.render_size();
+    let mut frame = vec![0; (width * height * 4) as usize];
+    world.render(&mut frame)?;
+
+    RgbaImage::from_raw(width, height, frame)
+        .ok_or_else(|| "rendered frame has the wrong size".into())
+}
+
+/// Render the world and save the result as the golden image for later comparisons
+pub fn save_golden(world: &mut World, golden_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    render_headless(world)?.save(golden_path)?;
+    Ok(())
+}
+
+/// Render the world and compare it to a golden image, allowing each channel to differ by `tolerance`.
+/// On a mismatch a diff image highlighting the differing pixels in red is written next to the golden.
+pub fn compare_frame(world: &mut World, golden_path: &Path, tolerance: u8) -> Result<(), Box<dyn std::error::Error>> {
+    let rendered = render_headless(world)?;
+    let golden = image::open(golden_path)?.to_rgba8();
+
+    if rendered.dimensions() != golden.dimensions() {
+        return Err(format!(
+            "rendered frame is {:?} but golden {} is {:?}",
+            rendered.dimensions(),
+            golden_path.display(),
+            golden.dimensions(),
+        ).into());
+    }
+
+    // Dim the rendered frame and mark every pixel outside the tolerance in red
+    let mut diff = RgbaImage::new(rendered.width(), rendered.height());
+    let mut mismatched = 0;
+    for ((x, y, rendered_pixel), golden_pixel) in rendered.enumerate_pixels().zip(golden.pixels()) {
+        let differs = rendered_pixel.0.iter()
+            .zip(golden_pixel.0.iter())
+            .any(|(a, b)| a.abs_diff(*b) > tolerance);
+
+        if differs {
+            mismatched += 1;
+            diff.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+        } else {
+            let [r, g, b, _] = rendered_pixel.0;
+            diff.put_pixel(x, y, Rgba([r / 4, g / 4, b / 4, 255]));
+        }
+    }
+
+    if mismatched == 0 {
+        return Ok(());
+    }
+
+    let diff_path = diff_path(golden_path);
+    diff.save(&diff_path)?;
+
+    Err(format!(
+        "{mismatched} pixels differ from {}, see {}",
+        golden_path.display(),
+        diff_path.display(),
+    ).into())
+}
+
+/// Panic with a description of the mismatch if the rendered world doesn't match the golden image
+pub fn assert_frame_matches(world: &mut World, golden_path: &Path, tolerance: u8) {
+    if let Err(err) = compare_frame(world, golden_path, tolerance) {
+        panic!("frame does not match golden image: {err}");
+    }
+}
+
+/// Plays scripted input into a world one physics step at a time, e.g. to run through a level headlessly
+pub struct ScriptRunner {
+    /// Input held over each range of frames, frames not covered by any range get no input
+    pub script: Vec<(Range<u64>, InputState)>,
+}
+
+impl ScriptRunner {
+    pub fn new(script: Vec<(Range<u64>, InputState)>) -> Self {
+        Self { script }
+    }
+
+    /// Step the world through every scripted frame and hand it back for checking the outcome.
+    /// Animations advance once a frame as if it had been drawn, set `ClockMode::Ticks` to make them repeatable.
+    pub fn run(&self, world: World) -> World {
+        self.run_inspecting(world, |_, _| {})
+    }
+
+    /// Like `run`, but hands the world to `inspect` after every frame so a test can check the whole path
+    pub fn run_inspecting(&self, mut world: World, mut inspect: impl FnMut(u64, &World)) -> World {
+        let last_frame = self.script.iter().map(|(frames, _)| frames.end).max().unwrap_or(0);
+        let step_time = 1.0 / world.physics_hz.max(1) as f64;
+
+        for frame in 0..last_frame {
+            let input = self.script
+                .iter()
+                .find(|(frames, _)| frames.contains(&frame))
+                .map_or(InputState::default(), |(_, input)| *input);
+
+            world.apply_input(input);
+            world.step(step_time);
+            world.update_sprite_animations();
+            inspect(frame, &world);
+        }
+
+        world
+    }
+}
+
+/// Where the diff image for a golden is written, e.g. `scene.png` becomes `scene.diff.png`
+fn diff_path(golden_path: &Path) -> PathBuf {
+    let stem = golden_path.file_stem().unwrap_or_default().to_string_lossy();
+    golden_path.with_file_name(format!("{stem}.diff.png"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{solid_sprite, test_world};
+    use crate::{Animation, ClockMode, DynamicImage, EngineEvent, Sprite, SpriteSheet, WORLD_HEIGHT};
+
+    #[test]
+    fn frame_matches_its_own_golden() {
+        let mut sprite = solid_sprite((8, 8), [255, 0, 0, 255]);
+        sprite.position = (20.0, 20.0);
+        let mut world = test_world(vec![sprite]);
+        let golden_path = std::env::temp_dir().join(format!("rustpixels-{}-golden.png", std::process::id()));
+
+        save_golden(&mut world, &golden_path).unwrap();
+        assert_frame_matches(&mut world, &golden_path, 0);
+
+        // Moving the sprite is caught, and the differing pixels are marked in the diff image
+        world.sprites[0].position.0 += 1.0;
+        assert!(compare_frame(&mut world, &golden_path, 0).is_err());
+        let diff = image::open(diff_path(&golden_path)).unwrap().to_rgba8();
+        assert_eq!(*diff.get_pixel(20, 20), Rgba([255, 0, 0, 255]));
+        assert_eq!(*diff.get_pixel(21, 20), Rgba([63, 0, 0, 255]));
+        assert_eq!(*diff.get_pixel(28, 20), Rgba([255, 0, 0, 255]));
+
+        std::fs::remove_file(diff_path(&golden_path)).unwrap();
+        std::fs::remove_file(&golden_path).unwrap();
+    }
+
+    /// The player standing on the floor left of an obstacle, with a repeatable animation clock
+    fn obstacle_scene(size: (u16, u16), x: f32) -> World {
+        let texture = DynamicImage::ImageRgba8(RgbaImage::from_pixel(150, 37, Rgba([255, 0, 0, 255])));
+        let mut player = Sprite::new(SpriteSheet::new(texture, vec![Animation::new((0, 0), 3, 10)], (50, 37)));
+        player.position = (20.0, (WORLD_HEIGHT - 37) as f32);
+
+        let mut obstacle = solid_sprite(size, [0, 0, 255, 255]);
+        obstacle.position = (x, (WORLD_HEIGHT - size.1 as u32) as f32);
+
+        let mut world = test_world(vec![player, obstacle]);
+        world.clock_mode = ClockMode::Ticks;
+        world
+    }
+
+    #[test]
+    fn scripted_run_and_jump_clears_a_crate() {
+        let run = InputState { left: false, right: true, jump: false };
+        let jump = InputState { left: false, right: true, jump: true };
+        let runner = ScriptRunner::new(vec![(0..30, run), (30..31, jump), (31..400, run)]);
+
+        // A 24x32 crate, which the default jump can get over
+        let floor = (WORLD_HEIGHT - 37) as f32;
+        let (roof, crate_span) = ((WORLD_HEIGHT - 32) as f32, 120.0..144.0);
+        let mut airborne = false;
+        let mut landed_at = None;
+        let mut world = runner.run_inspecting(obstacle_scene((24, 32), crate_span.start), |frame, world| {
+            let player = &world.sprites[0];
+            let (left, right, feet) = (player.position.0, player.position.0 + 50.0, player.position.1 + 37.0);
+
+            // Over the crate the player's feet never dip below its roof
+            if right > crate_span.start && left < crate_span.end {
+                assert!(feet <= roof, "frame {frame}: player at {:?} is inside the crate", player.position);
+            }
+
+            airborne |= player.position.1 < floor;
+            if airborne && landed_at.is_none() && player.position.1 == floor {
+                landed_at = Some(player.position);
+            }
+        });
+
+        // The first landing is on the floor past the crate
+        let landed_at = landed_at.expect("player never jumped");
+        assert!(landed_at.0 >= crate_span.end, "player landed at {landed_at:?}");
+        assert!(!world.poll_events().contains(&EngineEvent::Collision { a: 0, b: 1 }));
+        let player = &world.sprites[0];
+        assert_eq!((player.position.1, player.velocity.1), (floor, 0.0));
+
+        // One animation tick a frame, 400 ticks is 40 frames of 10 ticks
+        assert_eq!(player.sprite_sheet.animations[0].current_frame_index(), 1);
+
+        // Without the jump the player runs straight through it
+        let mut world = ScriptRunner::new(vec![(0..400, run)]).run(obstacle_scene((24, 32), crate_span.start));
+        assert!(world.poll_events().contains(&EngineEvent::Collision { a: 0, b: 1 }));
+    }
+
+    #[test]
+    fn default_jump_cannot_clear_the_building() {
+        // The building in the demo is 72 pixels tall and 98 wide, but the default jump only rises about 50
+        // pixels and covers less ground than the player's width plus the roof, so every timing hits it
+        let run = InputState { left: false, right: true, jump: false };
+        let jump = InputState { left: false, right: true, jump: true };
+        for jump_frame in 0..120 {
+            let runner = ScriptRunner::new(vec![(0..jump_frame, run), (jump_frame..jump_frame + 1, jump), (jump_frame + 1..400, run)]);
+            let mut world = runner.run(obstacle_scene((98, 72), 100.0));
+            assert!(world.poll_events().contains(&EngineEvent::Collision { a: 0, b: 1 }), "jumping on frame {jump_frame} cleared it");
+        }
+    }
+}